@@ -2,6 +2,7 @@ use anyhow::{anyhow, Result, Context};
 use concurrent_lru::unsharded::LruCache;
 use dssim_core::Dssim;
 use dssim_core::DssimImage;
+use dssim_core::Pooling;
 use rayon::prelude::*;
 use std::io::Write;
 use std::path::Path;
@@ -13,7 +14,7 @@ type AImage = Arc<DssimImage<f32>>;
 fn decode_png(dssim: &Dssim, path: PathBuf, cache: &LruCache<PathBuf, AImage>) -> Result<AImage> {
     let img = cache.get_or_try_init(path, 1, |path| {
         let png = lodepng::decode24_file(&path).with_context(|| format!("Can't read {}", path.display()))?;
-        let img = dssim.create_image_rgb(&png.buffer, png.width, png.height).ok_or(anyhow!("dssim fail"))?;
+        let img = dssim.create_image_rgb(&png.buffer, png.width, png.height)?;
         Ok::<_, anyhow::Error>(Arc::new(img))
     })?;
 
@@ -24,6 +25,11 @@ fn main() -> Result<()> {
     let csv_path = std::env::args_os().nth(1).map(PathBuf::from)
         .unwrap_or("clic_2021_perceptual_valid/validation.csv".into());
     let base_dir = csv_path.parent().unwrap_or(Path::new(""));
+    let (pooling, out_path) = match std::env::args().nth(2).as_deref() {
+        None | Some("mad") => (Pooling::MeanAbsDeviation, "dssim3.csv"),
+        Some("iw") => (Pooling::InformationWeighted, "dssim3-iw.csv"),
+        Some(other) => return Err(anyhow!("unknown pooling {}, expected mad or iw", other)),
+    };
 
     let cache = LruCache::new(30);
 
@@ -33,7 +39,8 @@ fn main() -> Result<()> {
 
     let results = validation_csv.records().par_bridge().map(|row| {
         let row = row?;
-        let dssim = Dssim::new();
+        let mut dssim = Dssim::new();
+        dssim.set_pooling(pooling)?;
         let o_file_name = row.get(0).ok_or(anyhow!("bad csv"))?;
         let a_file_name = row.get(1).ok_or(anyhow!("bad csv"))?;
         let b_file_name = row.get(2).ok_or(anyhow!("bad csv"))?;
@@ -41,8 +48,8 @@ fn main() -> Result<()> {
         let a_img = decode_png(&dssim, base_dir.join(a_file_name), &cache)?;
         let b_img = decode_png(&dssim, base_dir.join(b_file_name), &cache)?;
 
-        let a = dssim.compare(&o_img, &*a_img).0;
-        let b = dssim.compare(&o_img, &*b_img).0;
+        let a = dssim.compare(&o_img, &*a_img)?.0;
+        let b = dssim.compare(&o_img, &*b_img)?.0;
 
         writeln!(std::io::stdout().lock(), "{},{},{} = {} {}", o_file_name, a_file_name, b_file_name, a, b)?;
 
@@ -50,7 +57,7 @@ fn main() -> Result<()> {
     })
     .collect::<Vec<Result<_>>>();
 
-    let mut out = csv::Writer::from_path("dssim3.csv")?;
+    let mut out = csv::Writer::from_path(out_path)?;
     for r in results {
         let (o_name, a_name, b_name, a, b) = r?;
         out.write_record(&[o_name.as_str(), a_name.as_str(), b_name.as_str(), if a < b {"0"} else {"1"}])?;
@@ -69,5 +69,7 @@ void dssim_free_image(DssimImage *img);
  * `img1` can be reused for multiple comparisons.
  *
  * Don't forget to free the images and the DSSIM context when done.
+ *
+ * Returns NaN on error, e.g. if the images have different sizes.
  */
 double dssim_compare(struct Dssim *dssim, const DssimImage *img1, const DssimImage *img2);
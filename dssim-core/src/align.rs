@@ -0,0 +1,106 @@
+use imgref::*;
+
+/// Mean squared difference between `original(x, y)` and `modified(x + dx, y + dy)` in the area where they overlap.
+///
+/// `None` if the overlap is less than a quarter of the original.
+fn mean_sq_diff(original: ImgRef<'_, f32>, modified: ImgRef<'_, f32>, dx: isize, dy: isize) -> Option<f64> {
+    let x0 = (-dx).max(0) as usize;
+    let y0 = (-dy).max(0) as usize;
+    let x1 = (modified.width() as isize - dx).clamp(0, original.width() as isize) as usize;
+    let y1 = (modified.height() as isize - dy).clamp(0, original.height() as isize) as usize;
+    if x1 <= x0 || y1 <= y0 || (x1 - x0) * (y1 - y0) * 4 < original.width() * original.height() {
+        return None;
+    }
+
+    let mut sum = 0.;
+    for y in y0..y1 {
+        let orig_row = &original[y][x0..x1];
+        let mod_row = &modified[(y as isize + dy) as usize][(x0 as isize + dx) as usize..(x1 as isize + dx) as usize];
+        sum += orig_row.iter().zip(mod_row).map(|(&o, &m)| {
+            let diff = f64::from(o - m);
+            diff * diff
+        }).sum::<f64>();
+    }
+    Some(sum / ((x1 - x0) * (y1 - y0)) as f64)
+}
+
+/// Minimum of a parabola fitted to 3 points, relative to the middle one
+fn parabola_peak(minus: Option<f64>, center: f64, plus: Option<f64>) -> f64 {
+    if let (Some(minus), Some(plus)) = (minus, plus) {
+        let curvature = minus - 2. * center + plus;
+        if curvature > 0. {
+            let peak = (minus - plus) / (2. * curvature);
+            // Too small to matter, and not worth losing an edge row for
+            if peak.abs() >= 0.05 {
+                return peak.clamp(-0.5, 0.5);
+            }
+        }
+    }
+    0.
+}
+
+/// Find translation `(dx, dy)` for which `modified(x + dx, y + dy)` best matches `original(x, y)`.
+///
+/// Takes lightness planes of every scale, starting from the full-resolution one.
+/// The search starts at the smallest scale and refines the offset at every larger scale.
+pub(crate) fn find_offset(original: &[ImgRef<'_, f32>], modified: &[ImgRef<'_, f32>], max_shift: usize) -> (f64, f64) {
+    let levels = original.len().min(modified.len());
+    if levels == 0 || max_shift == 0 {
+        return (0., 0.);
+    }
+
+    let mut best = (0, 0);
+    for n in (0..levels).rev() {
+        // Shift allowed at this scale, rounded up
+        let limit = ((max_shift + (1 << n) - 1) >> n) as isize;
+        let (cx, cy, range) = if n == levels - 1 { (0, 0, limit) } else { (best.0 * 2, best.1 * 2, 1) };
+        let mut best_cost = f64::INFINITY;
+        for dy in (cy - range).max(-limit)..=(cy + range).min(limit) {
+            for dx in (cx - range).max(-limit)..=(cx + range).min(limit) {
+                let Some(cost) = mean_sq_diff(original[n], modified[n], dx, dy) else { continue };
+                // Prefer smaller shifts when the cost is the same
+                let closer = dx.abs() + dy.abs() < best.0.abs() + best.1.abs();
+                if cost < best_cost || (cost == best_cost && closer) {
+                    best_cost = cost;
+                    best = (dx, dy);
+                }
+            }
+        }
+        if !best_cost.is_finite() {
+            best = (cx, cy);
+        }
+    }
+
+    let (dx, dy) = best;
+    let cost = |dx, dy| mean_sq_diff(original[0], modified[0], dx, dy);
+    let Some(center) = cost(dx, dy) else { return (dx as f64, dy as f64) };
+    (
+        dx as f64 + parabola_peak(cost(dx - 1, dy), center, cost(dx + 1, dy)),
+        dy as f64 + parabola_peak(cost(dx, dy - 1), center, cost(dx, dy + 1)),
+    )
+}
+
+#[test]
+fn find_shift() {
+    let img = ImgVec::new((0..64 * 64).map(|i| {
+        let (x, y) = ((i % 64) as f32, (i / 64) as f32);
+        (x * 0.3).sin() * (y * 0.2).cos() * 0.5 + 0.5
+    }).collect(), 64, 64);
+    let shifted = img.as_ref().sub_image(5, 3, 55, 58);
+    let pyramid = |img: ImgRef<'_, f32>| {
+        let mut levels = vec![ImgVec::new(img.pixels().collect(), img.width(), img.height())];
+        while let Some(down) = crate::Downsample::downsample(levels.last().unwrap()) {
+            levels.push(down);
+        }
+        levels
+    };
+    let orig = pyramid(img.as_ref());
+    let modi = pyramid(shifted);
+    let orig: Vec<_> = orig.iter().map(|i| i.as_ref()).collect();
+    let modi: Vec<_> = modi.iter().map(|i| i.as_ref()).collect();
+
+    let (dx, dy) = find_offset(&orig, &modi, 8);
+    assert!((dx + 5.).abs() < 0.01 && (dy + 3.).abs() < 0.01, "{dx},{dy}");
+    assert_eq!((0., 0.), find_offset(&orig, &orig, 8));
+    assert_eq!((0., 0.), find_offset(&orig, &modi, 0));
+}
@@ -1,3 +1,6 @@
+use imgref::*;
+use std::mem::MaybeUninit;
+
 // 1D kernel from separable decomposition of the original 3×3 Gaussian
 // (KERNEL = [0.095332, 0.118095, 0.095332, …, 0.146293, …]).
 // Symmetric 1D form: K1D = [K_SIDE, K_CENTER, K_SIDE].
@@ -377,8 +380,166 @@ mod portable {
 
 pub use self::portable::*;
 
-#[cfg(test)]
-use imgref::*;
+/// Scales smaller than this are handled differently in small-image mode
+pub(crate) const SMALL_SCALE_SIZE: usize = 8;
+/// Mostly 3×3, so that the window doesn't cover most of a tiny image
+const SMALL_SCALE_SIGMA: f64 = 0.5;
+
+/// Size of the window for local statistics (average, variance) of pixels. See [`Dssim::set_blur_kernel()`][crate::Dssim::set_blur_kernel()]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum BlurKernel {
+    /// DSSIM's own 5×5 kernel (a 3×3 Gaussian applied twice). This is the default.
+    #[default]
+    Default,
+    /// Gaussian with the given standard deviation in pixels, truncated at 3σ.
+    ///
+    /// `1.5` gives the 11×11 window used in the SSIM paper. Must be in `0.1..=32` range.
+    Gaussian { sigma: f64 },
+}
+
+impl BlurKernel {
+    pub(crate) fn validate(self) -> Result<Self, crate::Error> {
+        match self {
+            Self::Gaussian { sigma } if !(sigma >= 0.1 && sigma <= 32.) => Err(crate::Error::InvalidParameter("Gaussian blur sigma must be in 0.1..=32 range")),
+            _ => Ok(self),
+        }
+    }
+
+    /// Kernel for a scale of the given size. In small-image mode, scales smaller than 8 pixels use a narrower window.
+    pub(crate) fn for_scale(self, small_images: bool, width: usize, height: usize) -> Self {
+        match self {
+            _ if !small_images || width.min(height) >= SMALL_SCALE_SIZE => self,
+            Self::Gaussian { sigma } if sigma <= SMALL_SCALE_SIGMA => self,
+            _ => Self::Gaussian { sigma: SMALL_SCALE_SIGMA },
+        }
+    }
+
+    /// Standard deviation of the blur in pixels
+    pub(crate) fn sigma(self) -> f64 {
+        match self {
+            // Variance of the 3-tap kernel is 2·K_SIDE, and it's applied twice
+            Self::Default => (4. * f64::from(K_SIDE)).sqrt(),
+            Self::Gaussian { sigma } => sigma,
+        }
+    }
+
+    /// How far from a pixel the blur reaches
+    pub(crate) fn radius(self) -> usize {
+        match self {
+            Self::Default => 2,
+            Self::Gaussian { sigma } => (3. * sigma).ceil() as usize,
+        }
+    }
+
+    pub(crate) fn blur(self, src: ImgRef<'_, f32>, tmp: &mut [MaybeUninit<f32>]) -> ImgVec<f32> {
+        match self {
+            Self::Default => blur(src, tmp),
+            Self::Gaussian { sigma } => separable::blur(src, tmp, &gaussian(sigma)),
+        }
+    }
+
+    pub(crate) fn blur_in_place(self, srcdst: ImgRefMut<'_, f32>, tmp: &mut [MaybeUninit<f32>]) {
+        match self {
+            Self::Default => blur_in_place(srcdst, tmp),
+            Self::Gaussian { sigma } => separable::blur_in_place(srcdst, tmp, &gaussian(sigma)),
+        }
+    }
+
+    pub(crate) fn blur_mul(self, src1: ImgRef<'_, f32>, src2: ImgRef<'_, f32>, tmp: &mut [MaybeUninit<f32>]) -> Vec<f32> {
+        match self {
+            Self::Default => blur_mul(src1, src2, tmp),
+            Self::Gaussian { sigma } => separable::blur_mul(src1, src2, tmp, &gaussian(sigma)),
+        }
+    }
+}
+
+/// Normalized 1D kernel, `2 * radius + 1` taps
+fn gaussian(sigma: f64) -> Vec<f32> {
+    let radius = BlurKernel::Gaussian { sigma }.radius() as isize;
+    let weights: Vec<f64> = (-radius..=radius).map(|i| (-(i * i) as f64 / (2. * sigma * sigma)).exp()).collect();
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|&w| (w / sum) as f32).collect()
+}
+
+/// Blur with an arbitrary symmetric 1D kernel applied horizontally and vertically.
+/// Pixels outside the image are replaced by the edge pixels.
+mod separable {
+    use imgref::*;
+    use std::mem::MaybeUninit;
+
+    fn blur_h(width: usize, height: usize, kernel: &[f32], dst: &mut [MaybeUninit<f32>], px: impl Fn(usize, usize) -> f32) {
+        let radius = kernel.len() / 2;
+        let last = width - 1;
+        for y in 0..height {
+            for (x, out) in dst[y * width..][..width].iter_mut().enumerate() {
+                let sum = kernel.iter().enumerate().fold(0., |sum, (i, &k)| {
+                    k.mul_add(px(y, (x + i).saturating_sub(radius).min(last)), sum)
+                });
+                out.write(sum);
+            }
+        }
+    }
+
+    /// `src` is tightly packed
+    fn blur_v(src: &[f32], width: usize, height: usize, kernel: &[f32], mut dst_row: impl FnMut(usize, &[f32])) {
+        let radius = kernel.len() / 2;
+        let last = height - 1;
+        let mut row = vec![0.; width];
+        for y in 0..height {
+            row.fill(0.);
+            for (i, &k) in kernel.iter().enumerate() {
+                let src_row = &src[(y + i).saturating_sub(radius).min(last) * width..][..width];
+                for (r, &s) in row.iter_mut().zip(src_row) {
+                    *r = k.mul_add(s, *r);
+                }
+            }
+            dst_row(y, &row);
+        }
+    }
+
+    /// SAFETY: every cell of `slice` must have been initialized.
+    unsafe fn assume_init(slice: &[MaybeUninit<f32>]) -> &[f32] {
+        // SAFETY: f32 and MaybeUninit<f32> have identical layout; caller guarantees init.
+        unsafe { std::slice::from_raw_parts(slice.as_ptr().cast::<f32>(), slice.len()) }
+    }
+
+    pub fn blur(src: ImgRef<'_, f32>, tmp: &mut [MaybeUninit<f32>], kernel: &[f32]) -> ImgVec<f32> {
+        let (width, height) = (src.width(), src.height());
+        let tmp = &mut tmp[..width * height];
+        blur_h(width, height, kernel, tmp, |y, x| src[y][x]);
+        // SAFETY: blur_h wrote every cell of tmp
+        let tmp = unsafe { assume_init(tmp) };
+        let mut out = Vec::with_capacity(width * height);
+        blur_v(tmp, width, height, kernel, |_, row| out.extend_from_slice(row));
+        ImgVec::new(out, width, height)
+    }
+
+    pub fn blur_in_place(mut srcdst: ImgRefMut<'_, f32>, tmp: &mut [MaybeUninit<f32>], kernel: &[f32]) {
+        let (width, height) = (srcdst.width(), srcdst.height());
+        let tmp = &mut tmp[..width * height];
+        {
+            let src = srcdst.as_ref();
+            blur_h(width, height, kernel, tmp, |y, x| src[y][x]);
+        }
+        // SAFETY: blur_h wrote every cell of tmp
+        let tmp = unsafe { assume_init(tmp) };
+        blur_v(tmp, width, height, kernel, |y, row| srcdst[y].copy_from_slice(row));
+    }
+
+    pub fn blur_mul(src1: ImgRef<'_, f32>, src2: ImgRef<'_, f32>, tmp: &mut [MaybeUninit<f32>], kernel: &[f32]) -> Vec<f32> {
+        let (width, height) = (src1.width(), src1.height());
+        debug_assert_eq!((width, height), (src2.width(), src2.height()));
+        let tmp = &mut tmp[..width * height];
+        blur_h(width, height, kernel, tmp, |y, x| src1[y][x] * src2[y][x]);
+        // SAFETY: blur_h wrote every cell of tmp
+        let tmp = unsafe { assume_init(tmp) };
+        let mut out = Vec::with_capacity(width * height);
+        blur_v(tmp, width, height, kernel, |_, row| out.extend_from_slice(row));
+        out
+    }
+}
 
 #[test]
 fn blur_zero() {
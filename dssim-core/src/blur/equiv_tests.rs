@@ -233,3 +233,63 @@
             }
         }
     }
+
+    /// Direct 2D convolution with edge-clamped reads, for checking the separable Gaussian
+    fn naive_gaussian(src: ImgRef<'_, f32>, sigma: f64) -> Vec<f64> {
+        let (w, h) = (src.width() as isize, src.height() as isize);
+        let radius = (3. * sigma).ceil() as isize;
+        let weight = |i: isize| (-(i * i) as f64 / (2. * sigma * sigma)).exp();
+        let norm: f64 = (-radius..=radius).map(weight).sum();
+        let mut out = Vec::with_capacity((w * h) as usize);
+        for y in 0..h {
+            for x in 0..w {
+                let mut sum = 0.;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let sx = (x + dx).clamp(0, w - 1) as usize;
+                        let sy = (y + dy).clamp(0, h - 1) as usize;
+                        sum += weight(dx) * weight(dy) * f64::from(src[sy][sx]);
+                    }
+                }
+                out.push(sum / (norm * norm));
+            }
+        }
+        out
+    }
+
+    fn max_diff(a: &[f32], b: impl IntoIterator<Item = f64>) -> f64 {
+        a.iter().zip(b).map(|(&a, b)| (f64::from(a) - b).abs()).fold(0., f64::max)
+    }
+
+    #[test]
+    fn gaussian_matches_naive() {
+        for &(w, h, sigma) in &[(40usize, 30usize, 0.5f64), (64, 48, 1.5), (37, 29, 3.), (5, 7, 1.5), (1, 9, 2.)] {
+            // Strided source
+            let full = random_image(w + 3, h, 0x600D_5EED_u32.wrapping_add(w as u32));
+            let src = full.as_ref().sub_image(2, 0, w, h);
+            let reference = naive_gaussian(src, sigma);
+            let kernel = super::BlurKernel::Gaussian { sigma };
+            let mut tmp: Vec<MaybeUninit<f32>> = (0..w * h).map(|_| MaybeUninit::uninit()).collect();
+
+            let out = kernel.blur(src, &mut tmp);
+            let diff = max_diff(out.buf(), reference.iter().copied());
+            assert!(diff < 1e-5, "{w}x{h} sigma={sigma}: {diff:.3e}");
+
+            let mut in_place = ImgVec::new(src.pixels().collect(), w, h);
+            kernel.blur_in_place(in_place.as_mut(), &mut tmp);
+            assert_eq!(out.buf(), in_place.buf());
+
+            let squared = kernel.blur_mul(src, src, &mut tmp);
+            let squared_ref = naive_gaussian(ImgVec::new(src.pixels().map(|p| p * p).collect(), w, h).as_ref(), sigma);
+            let diff = max_diff(&squared, squared_ref);
+            assert!(diff < 1e-5, "{w}x{h} sigma={sigma} mul: {diff:.3e}");
+        }
+    }
+
+    #[test]
+    fn default_kernel_is_fused_blur() {
+        let img = random_image(50, 40, 0x0DD_BA11);
+        let mut tmp: Vec<MaybeUninit<f32>> = (0..50 * 40).map(|_| MaybeUninit::uninit()).collect();
+        let fused = super::blur(img.as_ref(), &mut tmp);
+        assert_eq!(fused.buf(), super::BlurKernel::Default.blur(img.as_ref(), &mut tmp).buf());
+    }
@@ -34,8 +34,8 @@ pub unsafe extern "C" fn dssim_create_image_rgba(dssim: &mut Dssim, pixels: *con
     let height = height as usize;
     let pixels = unsafe { std::slice::from_raw_parts(pixels.cast::<RGBA8>(), width * height) };
     match dssim.create_image_rgba(pixels, width, height) {
-        Some(img) => Box::into_raw(Box::new(img)),
-        None => ptr::null_mut(),
+        Ok(img) => Box::into_raw(Box::new(img)),
+        Err(_) => ptr::null_mut(),
     }
 }
 
@@ -53,8 +53,8 @@ pub unsafe extern "C" fn dssim_create_image_rgb(dssim: &mut Dssim, pixels: *cons
     let height = height as usize;
     let pixels = unsafe { std::slice::from_raw_parts(pixels.cast::<RGB8>(), width * height) };
     match dssim.create_image_rgb(pixels, width, height) {
-        Some(img) => Box::into_raw(Box::new(img)),
-        None => ptr::null_mut(),
+        Ok(img) => Box::into_raw(Box::new(img)),
+        Err(_) => ptr::null_mut(),
     }
 }
 
@@ -72,10 +72,14 @@ pub unsafe extern "C" fn dssim_free_image(img: *mut DssimImage) {
 /// `img1` can be reused for multiple comparisons.
 ///
 /// Don't forget to free the images and the DSSIM context when done.
+///
+/// Returns NaN on error, e.g. if the images have different sizes.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn dssim_compare(dssim: &mut Dssim, img1: *const DssimImage, img2: *const DssimImage) -> f64 {
     let Some(img1) = (unsafe { img1.as_ref() }) else { return f64::NAN };
     let Some(img2) = (unsafe { img2.as_ref() }) else { return f64::NAN };
-    let (val, _) = dssim.compare(img1, img2);
-    val.into()
+    match dssim.compare(img1, img2) {
+        Ok((val, _)) => val.into(),
+        Err(_) => f64::NAN,
+    }
 }
@@ -0,0 +1,93 @@
+//! The standard SSIM and MS-SSIM, as in the reference MATLAB code by Wang et al.
+//!
+//! Images are compared on luma only, using the 11×11 Gaussian window, and scored
+//! only where the window fits entirely in the image (the "valid" area).
+
+use crate::blur::BlurKernel;
+use crate::dssim::{Algorithm, DssimChan, DssimChanScale};
+use crate::error::Error;
+use imgref::*;
+
+/// The reference window: σ = 1.5, truncated to 11×11
+pub(crate) const KERNEL: BlurKernel = BlurKernel::Gaussian { sigma: 1.5 };
+
+/// Pixels at each edge where the window doesn't fit
+const EDGE: usize = 5;
+
+/// Exponents of the contrast-structure terms of MS-SSIM (and of SSIM of the last scale)
+pub(crate) const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+pub(crate) fn num_scales(algorithm: Algorithm) -> usize {
+    match algorithm {
+        Algorithm::MsSsim => MS_SSIM_WEIGHTS.len(),
+        _ => 1,
+    }
+}
+
+/// Halves the size like the reference: averages 2×2 blocks, repeating the last row and column of odd sizes
+pub(crate) fn downsample(img: ImgRef<'_, f32>) -> ImgVec<f32> {
+    let (width, height) = (img.width().div_ceil(2), img.height().div_ceil(2));
+    let (last_x, last_y) = (img.width() - 1, img.height() - 1);
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let top = &img[2 * y];
+        let bot = &img[(2 * y + 1).min(last_y)];
+        out.extend((0..width).map(|x| {
+            let (x0, x1) = (2 * x, (2 * x + 1).min(last_x));
+            (top[x0] + top[x1] + bot[x0] + bot[x1]) * 0.25
+        }));
+    }
+    ImgVec::new(out, width, height)
+}
+
+/// Luma of all scales, preprocessed for comparison
+pub(crate) fn luma_scales(luma: ImgVec<f32>, algorithm: Algorithm) -> Result<Vec<DssimChanScale<f32>>, Error> {
+    let num_scales = num_scales(algorithm);
+    let mut scales = Vec::with_capacity(num_scales);
+    let mut img = luma;
+    loop {
+        let (width, height) = (img.width(), img.height());
+        if width == 0 || height == 0 {
+            return Err(Error::ZeroDimensions);
+        }
+        if width <= 2 * EDGE || height <= 2 * EDGE {
+            return Err(Error::InvalidParameter("image is too small for the 11×11 window at every scale"));
+        }
+        let next = (scales.len() + 1 < num_scales).then(|| downsample(img.as_ref()));
+
+        let mut ch = DssimChan::new(img, false);
+        let mut tmp = Vec::with_capacity(width * height);
+        ch.preprocess(&mut tmp.spare_capacity_mut()[..width * height], KERNEL, 0);
+        scales.push(DssimChanScale { chan: vec![ch] });
+
+        let Some(next) = next else { break };
+        img = next;
+    }
+    Ok(scales)
+}
+
+/// Only the area where the window fits entirely in the image
+pub(crate) fn valid_area(map: ImgRef<'_, f32>) -> ImgVec<f32> {
+    let area = map.sub_image(EDGE, EDGE, map.width() - 2 * EDGE, map.height() - 2 * EDGE);
+    ImgVec::new(area.pixels().collect(), area.width(), area.height())
+}
+
+/// Contrast-structure term of SSIM, `(2σ₁₂ + c2) / (σ₁² + σ₂² + c2)`
+pub(crate) fn cs_map(original: &DssimChan<f32>, modified: &DssimChan<f32>, img1_img2_blur: &[f32], c2: f32) -> ImgVec<f32> {
+    let map = img1_img2_blur.iter().zip(original.mu.iter().zip(&modified.mu)).zip(original.img_sq_blur.iter().zip(&modified.img_sq_blur))
+        .map(|((&img1_img2_blur, (&mu1, &mu2)), (&img1_sq_blur, &img2_sq_blur))| {
+            let sigma1_sq = mu1.mul_add(-mu1, img1_sq_blur);
+            let sigma2_sq = mu2.mul_add(-mu2, img2_sq_blur);
+            let sigma12 = mu1.mul_add(-mu2, img1_img2_blur);
+            2.0f32.mul_add(sigma12, c2) / (sigma1_sq + sigma2_sq + c2)
+        }).collect();
+    ImgVec::new(map, original.width, original.height)
+}
+
+#[test]
+fn downsample_odd() {
+    let img = ImgVec::new((0..15).map(|i| i as f32).collect(), 5, 3);
+    let half = downsample(img.as_ref());
+    assert_eq!((3, 2), (half.width(), half.height()));
+    assert_eq!(&[3., 5., 6.5, 10.5, 12.5, 14.][..], half.buf());
+}
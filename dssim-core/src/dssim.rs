@@ -19,28 +19,36 @@
  * If not, see <http://www.gnu.org/licenses/agpl.txt>.
  */
 
-use crate::blur;
+use crate::align;
+use crate::blur::{BlurKernel, SMALL_SCALE_SIZE};
+use crate::classic;
+use crate::error::Error;
 use crate::image::*;
-use crate::linear::ToRGBAPLU;
-pub use crate::tolab::ToLABBitmap;
+use crate::linear::{hlg_decode, pq_decode, to_linear, HdrTransfer, ToRGBAPLU, SDR_WHITE};
+use crate::metrics::{pixel_metrics, PixelMetrics};
+use crate::pooling::{information_weights, weighed_mean, Histogram, Pooling};
+use crate::resample::{resample, translate};
+pub use crate::tolab::{ColorModel, ColorSpace, CustomColorModel, ToLABBitmap};
+use crate::tolab::ImageBand;
 pub use crate::val::Dssim as Val;
+use crate::viewing::ViewingConditions;
 use imgref::*;
 #[cfg(not(feature = "threads"))]
 use crate::lieon as rayon;
 use rayon::prelude::*;
-use rgb::{RGB, RGBA};
-use std::borrow::Borrow;
+use rgb::{ComponentMap, RGB, RGBA};
+use std::borrow::{Borrow, Cow};
 use std::mem::MaybeUninit;
 use std::ops;
 use std::ops::Deref;
 use std::sync::Arc;
 
 trait Channable<T, I> {
-    fn img1_img2_blur(&self, modified: &Self, tmp: &mut [MaybeUninit<I>]) -> Vec<T>;
+    fn img1_img2_blur(&self, modified: &Self, tmp: &mut [MaybeUninit<I>], kernel: BlurKernel) -> Vec<T>;
 }
 
 #[derive(Clone)]
-struct DssimChan<T> {
+pub(crate) struct DssimChan<T> {
     pub width: usize,
     pub height: usize,
     pub img: Option<ImgVec<T>>,
@@ -50,24 +58,238 @@ struct DssimChan<T> {
 }
 
 /// Configuration for the comparison
+///
+/// With the `serde` feature it can be loaded from config files. Missing fields get default values.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default = "Dssim::new"))]
 pub struct Dssim {
     scale_weights: Vec<f64>,
     save_maps_scales: u8,
+    save_components: bool,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_pooling"))]
+    pooling: Pooling,
+    size_policy: SizePolicy,
+    max_alignment_shift: usize,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::positive"))]
+    k1: f64,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::positive"))]
+    k2: f64,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::positive"))]
+    dynamic_range: f64,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_blur_kernel"))]
+    blur_kernel: BlurKernel,
+    algorithm: Algorithm,
+    pixel_metrics: bool,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_color_space"))]
+    color_space: ColorSpace,
+    color_model: ColorModel,
+    hdr: bool,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_channel_weights"))]
+    channel_weights: [f64; 3],
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_chroma_blur"))]
+    chroma_blur: f64,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::serde_support::validated_alpha_mode"))]
+    alpha_mode: AlphaMode,
+    downsample_filter: DownsampleFilter,
+    small_images: bool,
+}
+
+/// Settings used by `make_scales_recursive` for every scale
+#[derive(Clone)]
+struct ScaleSettings {
+    blur_kernel: BlurKernel,
+    color_space: ColorSpace,
+    color_model: ColorModel,
+    hdr: bool,
+    chroma_blur: u8,
+    luma_only: bool,
+    downsample_filter: DownsampleFilter,
+    small_images: bool,
+}
+
+/// Stabilizing terms of the SSIM formula, `c1 = (k1·L)²` and `c2 = (k2·L)²`
+#[derive(Clone, Copy)]
+struct Constants {
+    c1: f32,
+    c2: f32,
+}
+
+/// Version of the scoring, so that scores can be compared across releases. See [`Dssim::with_algorithm_version()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum AlgorithmVersion {
+    /// Scoring of DSSIM 3.5.
+    ///
+    /// Scoring of releases before 3.5 isn't available.
+    /// Future releases that change the scoring will add new versions, and keep this one available.
+    V3_5,
+    /// Scoring of DSSIM 4.0. Same as 3.5, except images with odd width or height keep their last row and column in smaller scales.
+    #[default]
+    V4_0,
+}
+
+impl AlgorithmVersion {
+    /// The version used by [`Dssim::new()`]
+    pub const LATEST: Self = Self::V4_0;
+    const ALL: [Self; 2] = [Self::V3_5, Self::V4_0];
+}
+
+/// Which metric is computed. See [`Dssim::set_algorithm()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// Multi-scale SSIM of L\*a\*b\* channels, with weighed scales. This is the default.
+    #[default]
+    Dssim,
+    /// The standard SSIM by Wang et al. (2004), on luma only
+    Ssim,
+    /// The standard MS-SSIM by Wang et al. (2003), on luma only, with 5 scales
+    MsSsim,
+}
+
+/// What to do when the images to compare have different sizes. See [`Dssim::set_size_policy()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum SizePolicy {
+    /// Images must have the same size. This is the default.
+    #[default]
+    Exact,
+    /// Compare only the top-left area that both images have
+    CropToIntersection,
+    /// Compare only the area where the images overlap, with the modified image's top-left corner
+    /// placed at the given position in the original image (can be negative)
+    CropAt { x: isize, y: isize },
+    /// Scale the modified image to the size of the original. It's done in linear light.
+    Resample,
+}
+
+/// How transparent pixels are compared. See [`Dssim::set_alpha_mode()`]
+///
+/// Colors are sRGB-encoded. The background is used for both images, so it only matters where their alpha differs, or where it's translucent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum AlphaMode {
+    /// Composite over a pattern of colors: red, green, and blue channels are switched on and off in stripes 8, 16, and 32 pixels wide,
+    /// repeated at every scale. This makes changes of alpha visible against most colors. This is the default.
+    #[default]
+    Pattern,
+    /// Composite over a solid color
+    Background([u8; 3]),
+    /// Composite over a checkerboard with squares of `size` pixels, starting with the `light` one in the top-left corner
+    Checkerboard { size: u16, light: [u8; 3], dark: [u8; 3] },
+    /// Compare color premultiplied by alpha (i.e. over black), and alpha as a separate channel. `weight` is relative to the color channels together.
+    ///
+    /// Opaque images get an opaque alpha channel, so they can be compared with transparent ones.
+    Separate { weight: f64 },
+}
+
+impl AlphaMode {
+    pub(crate) fn validate(self) -> Result<Self, Error> {
+        match self {
+            Self::Checkerboard { size: 0, .. } => Err(Error::InvalidParameter("checkerboard size must be greater than zero")),
+            Self::Separate { weight } if !(weight.is_finite() && weight >= 0.) => Err(Error::InvalidParameter("alpha weight must be finite and non-negative")),
+            _ => Ok(self),
+        }
+    }
+
+    /// Linear background of every `x, y`, scaled by `white`. `None` for the pattern that's applied by `ToLABBitmap`.
+    fn background(self, white: f32) -> Option<Box<dyn Fn(usize, usize) -> RGBLU + Sync>> {
+        let linear = move |c: [u8; 3]| RGBLU::from(c.map(|c| to_linear(f32::from(c) / 255.) * white));
+        match self {
+            Self::Pattern => None,
+            Self::Background(c) => {
+                let c = linear(c);
+                Some(Box::new(move |_, _| c))
+            },
+            Self::Checkerboard { size, light, dark } => {
+                let (size, light, dark) = (usize::from(size), linear(light), linear(dark));
+                Some(Box::new(move |x, y| if (x / size + y / size) % 2 == 0 { light } else { dark }))
+            },
+            Self::Separate { .. } => Some(Box::new(|_, _| RGBLU::new(0., 0., 0.))),
+        }
+    }
+}
+
+/// How the images have been adjusted to have the same size. See [`SizePolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SizeAdjustment {
+    /// The images had the same size
+    #[default]
+    None,
+    /// Only this area of the original image has been compared
+    Cropped { left: usize, top: usize, width: usize, height: usize },
+    /// The modified image has been resampled from this size
+    Resampled { width: usize, height: usize },
+}
+
+/// Translation found by [`Dssim::compare_images()`] when alignment is enabled. See [`Dssim::set_alignment()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alignment {
+    /// Horizontal offset of the modified image's content: its pixel at `x + dx` matches the original's `x`
+    pub dx: f64,
+    /// Vertical offset of the modified image's content: its pixel at `y + dy` matches the original's `y`
+    pub dy: f64,
+    /// Left edge of the compared area, in the original image's coordinates
+    pub left: usize,
+    /// Top edge of the compared area, in the original image's coordinates
+    pub top: usize,
+    /// Width of the compared area
+    pub width: usize,
+    /// Height of the compared area
+    pub height: usize,
 }
 
 #[derive(Clone)]
-struct DssimChanScale<T> {
-    chan: Vec<DssimChan<T>>,
+pub(crate) struct DssimChanScale<T> {
+    pub chan: Vec<DssimChan<T>>,
 }
 
 /// Abstract wrapper for images. See [`Dssim::create_image()`]
 #[derive(Clone)]
 pub struct DssimImage<T> {
-    scale: Vec<DssimChanScale<T>>,
+    pub(crate) scale: Vec<DssimChanScale<T>>,
+    /// Pooling weights for every scale
+    pub(crate) weight_mask: Option<Vec<ImgVec<f32>>>,
+    pub(crate) blur_kernel: BlurKernel,
+    pub(crate) algorithm: Algorithm,
+    /// Full-resolution pixels for [`PixelMetrics`], if enabled
+    pub(crate) pixels: Option<ImgVec<RGBLU>>,
+    pub(crate) color_space: ColorSpace,
+    pub(crate) color_model: ColorModel,
+    /// How many times chroma has been blurred before comparison
+    pub(crate) chroma_blur: u8,
+    /// Compared in ICtCp instead of L\*a\*b\*
+    pub(crate) hdr: bool,
+    pub(crate) alpha_mode: AlphaMode,
+    /// Alpha channel of every scale, if it's compared separately
+    pub(crate) alpha: Option<Vec<DssimChan<T>>>,
+    /// Used for the weight mask and alpha too
+    pub(crate) downsample_filter: DownsampleFilter,
+    /// See [`Dssim::set_small_image_mode()`]
+    pub(crate) small_images: bool,
+    /// Version of the scoring that the image has been created for, if the settings for creating it haven't been customized
+    pub(crate) algorithm_version: Option<AlgorithmVersion>,
 }
 
 impl<T> DssimImage<T> {
+    /// Blur kernel used for the scale `n`
+    pub(crate) fn scale_kernel(&self, n: usize) -> BlurKernel {
+        let chan = &self.scale[n].chan[0];
+        self.blur_kernel.for_scale(self.small_images, chan.width, chan.height)
+    }
+
+    /// Pooling used for the scale `n`
+    pub(crate) fn scale_pooling(&self, pooling: Pooling, n: usize) -> Pooling {
+        let chan = &self.scale[n].chan[0];
+        pooling.for_scale(self.small_images, chan.width, chan.height)
+    }
+
     #[inline]
     #[must_use]
     pub fn width(&self) -> usize {
@@ -81,18 +303,149 @@ impl<T> DssimImage<T> {
     }
 }
 
+impl DssimImage<f32> {
+    /// Make some areas of the image more important than others.
+    ///
+    /// The mask has per-pixel weights, and must have the same size as the image. It's downsampled along with the image for every scale.
+    /// When this image is used as the original in a comparison, SSIM of each scale is pooled using weighed mean and weighed deviation.
+    /// Pixels with weight 0 are excluded from the score entirely.
+    ///
+    /// Weights must be finite and non-negative.
+    pub fn set_weight_mask(&mut self, mask: ImgRef<'_, f32>) -> Result<(), Error> {
+        if mask.width() != self.width() || mask.height() != self.height() {
+            return Err(Error::SizeMismatch { original: (self.width(), self.height()), modified: (mask.width(), mask.height()) });
+        }
+        if !mask.pixels().all(|w| w.is_finite() && w >= 0.) {
+            return Err(Error::InvalidParameter("mask weights must be finite and non-negative"));
+        }
+
+        let mut masks = Vec::with_capacity(self.scale.len());
+        masks.push(ImgVec::new(mask.pixels().collect(), mask.width(), mask.height()));
+        for s in &self.scale[1..] {
+            let down = masks.last().and_then(|m: &ImgVec<f32>| match self.algorithm {
+                    // Lanczos can ring below zero
+                    Algorithm::Dssim => m.downsample_with(self.downsample_filter).map(|m| m.map_buf(|buf| buf.into_iter().map(|w| w.max(0.)).collect())),
+                    _ => Some(classic::downsample(m.as_ref())),
+                })
+                .filter(|m| m.width() == s.chan[0].width && m.height() == s.chan[0].height)
+                .ok_or(Error::ScaleCountMismatch { original: self.scale.len(), modified: masks.len() })?;
+            masks.push(down);
+        }
+        self.weight_mask = Some(masks);
+        Ok(())
+    }
+
+    /// Remove the mask set by [`DssimImage::set_weight_mask()`]
+    pub fn clear_weight_mask(&mut self) {
+        self.weight_mask = None;
+    }
+}
+
+/// Standard deviation of the chroma blur, about one pass of the default blur kernel
+const DEFAULT_CHROMA_BLUR: f64 = 1.1;
+
 // Weighed scales are inspired by the IW-SSIM, but details of the algorithm and weights are different
 const DEFAULT_WEIGHTS: [f64; 5] = [0.028, 0.197, 0.322, 0.298, 0.155];
 
 /// Detailed comparison result
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SsimMap {
     /// SSIM scores
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::map"))]
     pub map: ImgVec<f32>,
     /// Average SSIM (not DSSIM)
     pub ssim: f64,
 }
 
+/// Breakdown of the comparison result. See [`Dssim::compare_report()`]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComparisonReport {
+    /// The final score, same as returned by [`Dssim::compare()`]
+    pub dssim: Val,
+    /// The final score as similarity, `1 / (1 + dssim)`. For [`Algorithm::Ssim`] and [`Algorithm::MsSsim`] it's the standard SSIM or MS-SSIM value.
+    pub ssim: f64,
+    /// Scores of each scale, starting from the full-resolution one
+    pub scales: Vec<ScaleReport>,
+    /// Set by [`Dssim::compare_images()`] if the images had to be cropped or resampled
+    pub size_adjustment: SizeAdjustment,
+    /// Set by [`Dssim::compare_images()`] if alignment is enabled
+    pub alignment: Option<Alignment>,
+    /// Version of the scoring, if the settings affecting the score, including the settings the images have been created with, are the same as in that version.
+    /// `None` if the settings have been customized. See [`Dssim::algorithm_version()`]
+    pub algorithm_version: Option<AlgorithmVersion>,
+    /// PSNR and color difference, if enabled with [`Dssim::set_pixel_metrics()`]
+    pub pixel_metrics: Option<PixelMetrics>,
+    /// How transparent pixels have been compared
+    pub alpha_mode: AlphaMode,
+    /// Number of configured scales that haven't been compared, because the images are too small for them.
+    /// See [`Dssim::set_small_image_mode()`]
+    pub skipped_scales: usize,
+}
+
+/// Outcome of [`Dssim::compare_with_threshold()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThresholdResult {
+    /// DSSIM is at most the threshold
+    pub passed: bool,
+    /// The lowest score the images could have, given the scales that have been compared
+    pub min_dssim: Val,
+    /// The highest score the images could have. Same as `min_dssim` if all scales have been compared.
+    pub max_dssim: Val,
+    /// Number of scales that have been compared, starting from the smallest one
+    pub scales_compared: usize,
+}
+
+/// Score of a single scale of the comparison
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaleReport {
+    /// Width of the image at this scale
+    pub width: usize,
+    /// Height of the image at this scale
+    pub height: usize,
+    /// Pooled SSIM of all channels (not DSSIM)
+    pub ssim: f64,
+    /// Weight of this scale. The final SSIM is a weighed average of the scales' `ssim`.
+    pub weight: f64,
+    /// Pooled SSIM of the lightness channel alone
+    pub luma_ssim: f64,
+    /// Pooled SSIM of the a\*b\* channels. `None` for grayscale images.
+    pub chroma_ssim: Option<f64>,
+    /// Pooled SSIM of the alpha channel, if it's compared separately. See [`AlphaMode::Separate`]
+    pub alpha_ssim: Option<f64>,
+    /// Per-pixel scores, if enabled with [`Dssim::set_save_ssim_maps()`]
+    pub ssim_map: Option<SsimMap>,
+    /// SSIM split into separate terms, if enabled with [`Dssim::set_ssim_components()`]
+    pub components: Option<SsimComponents>,
+}
+
+/// Terms of the SSIM formula, which multiplied together give the SSIM score.
+///
+/// Luminance is affected by brightness/color shifts, contrast by loss of detail or added noise,
+/// and structure by misplaced or distorted detail.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SsimComponents {
+    /// Average of `luminance_map`
+    pub luminance: f64,
+    /// Average of `contrast_map`
+    pub contrast: f64,
+    /// Average of `structure_map`
+    pub structure: f64,
+    /// `(2μ₁μ₂ + c1) / (μ₁² + μ₂² + c1)`
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::map"))]
+    pub luminance_map: ImgVec<f32>,
+    /// `(2σ₁σ₂ + c2) / (σ₁² + σ₂² + c2)`
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::map"))]
+    pub contrast_map: ImgVec<f32>,
+    /// `(σ₁₂ + c2/2) / (σ₁σ₂ + c2/2)`
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::map"))]
+    pub structure_map: ImgVec<f32>,
+}
+
 /// Create new context for a comparison
 #[must_use]
 pub fn new() -> Dssim {
@@ -101,7 +454,8 @@ pub fn new() -> Dssim {
 
 impl DssimChan<f32> {
     pub fn new(bitmap: ImgVec<f32>, is_chroma: bool) -> Self {
-        debug_assert!(bitmap.pixels().all(|i| i.is_finite() && i >= 0.0 && i <= 1.0));
+        // Colors outside of the sRGB gamut can be outside of 0..1
+        debug_assert!(bitmap.pixels().all(f32::is_finite));
 
         Self {
             width: bitmap.width(),
@@ -115,7 +469,7 @@ impl DssimChan<f32> {
 }
 
 impl DssimChan<f32> {
-    fn preprocess(&mut self, tmp: &mut [MaybeUninit<f32>]) {
+    pub(crate) fn preprocess(&mut self, tmp: &mut [MaybeUninit<f32>], kernel: BlurKernel, chroma_blur: u8) {
         let width = self.width;
         let height = self.height;
         assert!(width > 0);
@@ -126,35 +480,74 @@ impl DssimChan<f32> {
         debug_assert!(img.pixels().all(f32::is_finite));
 
         if self.is_chroma {
-            blur::blur_in_place(img.as_mut(), tmp);
+            for _ in 0..chroma_blur {
+                kernel.blur_in_place(img.as_mut(), tmp);
+            }
         }
-        let (mu, ..) = blur::blur(img.as_ref(), tmp).into_contiguous_buf();
+        let (mu, ..) = kernel.blur(img.as_ref(), tmp).into_contiguous_buf();
         self.mu = mu;
 
         // Fused squared-image blur: blur_mul(img, img) does a single H5*V5 pass
         // over img*img, avoiding both the materialized i*i vector and the
         // separate in-place blur over it.
-        self.img_sq_blur = blur::blur_mul(img.as_ref(), img.as_ref(), tmp);
+        self.img_sq_blur = kernel.blur_mul(img.as_ref(), img.as_ref(), tmp);
         debug_assert_eq!(self.img_sq_blur.len(), width * height);
     }
 }
 
 impl Channable<f32, f32> for DssimChan<f32> {
-    fn img1_img2_blur(&self, modified: &Self, tmp32: &mut [MaybeUninit<f32>]) -> Vec<f32> {
+    fn img1_img2_blur(&self, modified: &Self, tmp32: &mut [MaybeUninit<f32>], kernel: BlurKernel) -> Vec<f32> {
         let src = self.img.as_ref().unwrap();
         let modified_img = modified.img.as_ref().unwrap();
         // Fused multiply+blur: avoids materializing the product as a Vec.
-        blur::blur_mul(src.as_ref(), modified_img.as_ref(), tmp32)
+        kernel.blur_mul(src.as_ref(), modified_img.as_ref(), tmp32)
     }
 }
 
 impl Dssim {
-    /// Create new context for comparisons
+    /// Create new context for comparisons, using the latest version of the scoring
     #[must_use]
     pub fn new() -> Self {
+        Self::with_algorithm_version(AlgorithmVersion::LATEST)
+    }
+
+    /// Create new context for comparisons, with settings that give the same scores as the given version.
+    ///
+    /// Changing scales, pooling, SSIM constants, blur kernel, downsample filter, or the algorithm makes the scores differ from that version.
+    /// Use [`Dssim::algorithm_version()`] to check whether they still match.
+    #[must_use]
+    pub fn with_algorithm_version(version: AlgorithmVersion) -> Self {
+        match version {
+            AlgorithmVersion::V3_5 => Self::v3_5(),
+            AlgorithmVersion::V4_0 => Self {
+                downsample_filter: DownsampleFilter::Box,
+                ..Self::v3_5()
+            },
+        }
+    }
+
+    fn v3_5() -> Self {
         Self {
             scale_weights: DEFAULT_WEIGHTS[..].to_owned(),
             save_maps_scales: 0,
+            save_components: false,
+            pooling: Pooling::default(),
+            size_policy: SizePolicy::default(),
+            max_alignment_shift: 0,
+            k1: 0.01,
+            k2: 0.03,
+            dynamic_range: 1.,
+            blur_kernel: BlurKernel::Default,
+            algorithm: Algorithm::Dssim,
+            pixel_metrics: false,
+            color_space: ColorSpace::Srgb,
+            color_model: ColorModel::Lab,
+            hdr: false,
+            channel_weights: [1.; 3],
+            chroma_blur: DEFAULT_CHROMA_BLUR,
+            alpha_mode: AlphaMode::Pattern,
+            downsample_filter: DownsampleFilter::BoxTruncating,
+            small_images: false,
         }
     }
 
@@ -163,33 +556,297 @@ impl Dssim {
         self.scale_weights = scales.to_vec();
     }
 
+    /// Set scales and their weights for images seen at the given resolution, instead of the default weights.
+    ///
+    /// Fewer scales are used for images viewed from afar, and fine details matter less for dense displays.
+    /// See [`ViewingConditions::scale_weights()`].
+    pub fn set_viewing_conditions(&mut self, conditions: ViewingConditions) {
+        self.scale_weights = conditions.scale_weights();
+    }
+
     /// Set how many scales will be kept for saving
     pub fn set_save_ssim_maps(&mut self, num_scales: u8) {
         self.save_maps_scales = num_scales;
     }
 
+    /// Make [`Dssim::compare_report()`] include luminance, contrast and structure terms of SSIM for every scale.
+    ///
+    /// It's off by default, because it needs another pass over the data and keeps three maps per scale.
+    pub fn set_ssim_components(&mut self, enabled: bool) {
+        self.save_components = enabled;
+    }
+
+    /// Set how SSIM of pixels is combined into a score of each scale. The default is [`Pooling::MeanAbsDeviation`].
+    ///
+    /// Fails if the pooling parameter is out of range.
+    pub fn set_pooling(&mut self, pooling: Pooling) -> Result<(), Error> {
+        self.pooling = pooling.validate()?;
+        Ok(())
+    }
+
+    /// Allow [`Dssim::compare_images()`] to compare images of different sizes
+    pub fn set_size_policy(&mut self, policy: SizePolicy) {
+        self.size_policy = policy;
+    }
+
+    /// Set parameters of the constants that stabilize the SSIM formula for areas with low brightness or contrast:
+    /// `c1 = (k1·L)²` and `c2 = (k2·L)²`, where `L` is the dynamic range of pixel values.
+    ///
+    /// The defaults are `k1 = 0.01`, `k2 = 0.03` and `L = 1`, as in the original SSIM paper,
+    /// with `L` matching the range of the lightness channel (0..1). Smaller values make dark and flat areas more sensitive to differences.
+    ///
+    /// All values must be finite and greater than zero.
+    pub fn set_ssim_constants(&mut self, k1: f64, k2: f64, dynamic_range: f64) -> Result<(), Error> {
+        if ![k1, k2, dynamic_range].iter().all(|&v| v.is_finite() && v > 0.) {
+            return Err(Error::InvalidParameter("SSIM constants must be finite and greater than zero"));
+        }
+        self.k1 = k1;
+        self.k2 = k2;
+        self.dynamic_range = dynamic_range;
+        Ok(())
+    }
+
+    /// Set size of the window used for local averages and variances of pixels. The default is a 5×5 window.
+    ///
+    /// Larger windows are less sensitive to fine noise, and smaller ones may be better for tiny images.
+    /// Images must be created and compared using the same kernel.
+    ///
+    /// Fails if the sigma is out of range.
+    pub fn set_blur_kernel(&mut self, kernel: BlurKernel) -> Result<(), Error> {
+        self.blur_kernel = kernel.validate()?;
+        Ok(())
+    }
+
+    /// Set the filter used for making smaller scales of images, and their weight masks. The default is [`DownsampleFilter::Box`].
+    ///
+    /// Images must be created and compared using the same filter. The standard SSIM algorithms use their own downsampling.
+    pub fn set_downsample_filter(&mut self, filter: DownsampleFilter) {
+        self.downsample_filter = filter;
+    }
+
+    /// Compare images too small for all the scales, such as icons, in a defined way.
+    ///
+    /// Images smaller than 8×8 pixels aren't downsampled further, so small images have fewer scales than configured.
+    /// By default the missing scales are skipped, and the weights of the remaining ones are normalized, so the finest scales dominate the score.
+    /// In this mode, the number of scales is picked from the smaller side of the image, so that no scale is smaller than 8 pixels,
+    /// and the smallest scale gets the weights of the missing ones. Images smaller than 8 pixels have one scale with a narrower blur window,
+    /// so that local statistics aren't dominated by the edges of the image. It's pooled with a plain average instead of [`Pooling::MeanAbsDeviation`]
+    /// (or other pooling, except [`Pooling::InformationWeighted`]), so that a uniform change of a tiny image isn't scored as no change.
+    /// [`ComparisonReport::skipped_scales`] tells how many scales have been missing.
+    ///
+    /// It doesn't change scores of images large enough for all the scales. Images must be created and compared in the same mode.
+    pub fn set_small_image_mode(&mut self, enabled: bool) {
+        self.small_images = enabled;
+    }
+
+    /// Compute the standard SSIM or MS-SSIM instead of DSSIM, for comparisons with other tools.
+    ///
+    /// These algorithms use gamma-encoded luma (Y′ = 0.299R′ + 0.587G′ + 0.114B′) and the 11×11 Gaussian window with σ = 1.5.
+    /// Like the reference implementation, only pixels where the window fits in the image are scored, and the score is their mean.
+    /// Images aren't downsampled automatically, so scale large images yourself if you need to match the recommended viewing distance.
+    /// Scales, blur kernel, and pooling settings are ignored. The SSIM constants are used, and the defaults are the same as the reference.
+    ///
+    /// The score is in [`ComparisonReport::ssim`]. Images must be created and compared with the same algorithm.
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Make [`Dssim::compare_report()`] also compute MSE and PSNR (in sRGB and linear RGB) and CIEDE2000 color difference.
+    ///
+    /// Images created with this enabled keep a copy of their full-resolution pixels, which takes 12 bytes per pixel.
+    /// The metrics are computed only when both images have it. Weight masks are not used for these metrics.
+    pub fn set_pixel_metrics(&mut self, enabled: bool) {
+        self.pixel_metrics = enabled;
+    }
+
+    /// Set primaries of the RGB pixels of images that will be created. The default is sRGB.
+    ///
+    /// Colors are converted to L\*a\*b\* using the primaries, so images in different color spaces can be compared with each other,
+    /// and colors outside of the sRGB gamut aren't clipped.
+    ///
+    /// [`Dssim::create_image_rgba()`] and [`Dssim::create_image_rgb()`] decode the pixels using the sRGB curve, which is also used by Display P3.
+    /// For other curves, convert the pixels to linear light yourself, and use [`Dssim::create_image()`].
+    /// The standard SSIM algorithms and PSNR use the pixel values as they are, without conversion.
+    ///
+    /// Fails if the custom matrix is invalid.
+    pub fn set_color_space(&mut self, color_space: ColorSpace) -> Result<(), Error> {
+        self.color_space = color_space.validate()?;
+        Ok(())
+    }
+
+    /// Set the color model in which the images are compared. The default is L\*a\*b\*.
+    ///
+    /// The first plane of every model is lightness, and the other two are chroma, which is blurred before comparison like the chroma of L\*a\*b\*.
+    /// Images created with different models can't be compared with each other.
+    /// The standard SSIM algorithms always use luma, and the HDR mode supports only its own model.
+    pub fn set_color_model(&mut self, model: ColorModel) {
+        self.color_model = model;
+    }
+
+    /// Set how much lightness and the two chroma channels contribute to the score. The default is `[1., 1., 1.]`.
+    ///
+    /// The weights are relative to each other. When both chroma weights are zero, images are created with lightness only,
+    /// which skips blurring and comparing the chroma, and is faster. Such images can't be compared with color images.
+    ///
+    /// Weights must be finite, non-negative, and not all zero.
+    pub fn set_channel_weights(&mut self, weights: [f64; 3]) -> Result<(), Error> {
+        self.channel_weights = validate_channel_weights(weights)?;
+        Ok(())
+    }
+
+    /// Set how much chroma is blurred before comparison, which makes it less sensitive to fine details of color,
+    /// similarly to human vision. It's the standard deviation of the blur in pixels, like in [`BlurKernel::Gaussian`]. The default is `1.1`.
+    ///
+    /// Use `0` if chroma subsampling errors matter, e.g. for graphics and text, and more for photos where they don't.
+    /// Chroma is blurred with the [blur kernel](Dssim::set_blur_kernel()) as many times as needed to get closest to this sigma,
+    /// so sigmas much smaller than the kernel's round to no blur.
+    /// Images must be created and compared with the same setting.
+    ///
+    /// Fails if it's not in `0..=4` range.
+    pub fn set_chroma_blur(&mut self, sigma: f64) -> Result<(), Error> {
+        self.chroma_blur = validate_chroma_blur(sigma)?;
+        Ok(())
+    }
+
+    /// How many times chroma is blurred with the blur kernel. Variances of the blurs add up.
+    fn chroma_blur_passes(&self) -> u8 {
+        (self.chroma_blur / self.blur_kernel.sigma()).powi(2).round().min(255.) as u8
+    }
+
+    /// Set how transparent pixels are compared. The default is [`AlphaMode::Pattern`].
+    ///
+    /// Images must be created and compared with the same mode. [`Dssim::compare_tiled()`] doesn't support [`AlphaMode::Separate`].
+    ///
+    /// Fails if the checkerboard size is zero, or the alpha weight is negative.
+    pub fn set_alpha_mode(&mut self, mode: AlphaMode) -> Result<(), Error> {
+        self.alpha_mode = mode.validate()?;
+        Ok(())
+    }
+
+    /// Normalized channel weights
+    fn channel_weights(&self) -> [f32; 3] {
+        let sum: f64 = self.channel_weights.iter().sum();
+        self.channel_weights.map(|w| (w / sum) as f32)
+    }
+
+    /// Compare images in the HDR mode: pixels given to [`Dssim::create_image()`] are absolute linear light, where 1.0 is 10000 cd/m²,
+    /// and they're compared in PQ-encoded ICtCp instead of L\*a\*b\*. Brightness above SDR white is compared as precisely as below it.
+    ///
+    /// [`Dssim::create_image_rgba()`] and [`Dssim::create_image_rgb()`] put SDR white at 203 cd/m², so SDR images can be compared with HDR ones.
+    /// Use [`Dssim::create_image_hdr()`] for PQ- and HLG-encoded pixels.
+    ///
+    /// The SSIM constants are relative to the whole PQ range, so the scores aren't comparable with scores of the SDR mode.
+    /// The standard SSIM algorithms and pixel metrics are not supported in this mode.
+    pub fn set_hdr(&mut self, enabled: bool) {
+        self.hdr = enabled;
+    }
+
+    /// Version of the scoring that these settings reproduce, or `None` if the settings affecting the score have been customized.
+    ///
+    /// It's also in [`ComparisonReport::algorithm_version`].
+    #[must_use]
+    pub fn algorithm_version(&self) -> Option<AlgorithmVersion> {
+        AlgorithmVersion::ALL.into_iter().rev().find(|&version| self.same_scoring(&Self::with_algorithm_version(version)))
+    }
+
+    fn same_scoring(&self, pinned: &Self) -> bool {
+        self.same_image_settings(pinned, self.hdr)
+            && self.scale_weights == pinned.scale_weights
+            && self.pooling == pinned.pooling
+            && (self.k1, self.k2, self.dynamic_range) == (pinned.k1, pinned.k2, pinned.dynamic_range)
+            && self.channel_weights == pinned.channel_weights
+    }
+
+    /// Version of the scoring that images created with these settings are for. Recorded in images, because they can be created with other settings than they're compared with.
+    fn image_algorithm_version(&self, hdr: bool) -> Option<AlgorithmVersion> {
+        AlgorithmVersion::ALL.into_iter().rev().find(|&version| self.same_image_settings(&Self::with_algorithm_version(version), hdr))
+    }
+
+    /// Settings used by `create_image`
+    fn same_image_settings(&self, pinned: &Self, hdr: bool) -> bool {
+        self.scale_weights.len() == pinned.scale_weights.len()
+            && self.blur_kernel == pinned.blur_kernel
+            && self.algorithm == pinned.algorithm
+            && self.color_model == pinned.color_model
+            && hdr == pinned.hdr
+            && (self.channel_weights[1..] == [0., 0.]) == (pinned.channel_weights[1..] == [0., 0.])
+            && self.chroma_blur_passes() == pinned.chroma_blur_passes()
+            && self.alpha_mode == pinned.alpha_mode
+            && self.downsample_filter == pinned.downsample_filter
+            && self.small_images == pinned.small_images
+    }
+
+    /// Version of the scoring, if the images have been created for it too
+    fn report_algorithm_version(&self, original_image: &DssimImage<f32>, modified_image: &DssimImage<f32>) -> Option<AlgorithmVersion> {
+        self.algorithm_version().filter(|&version| original_image.algorithm_version == Some(version) && modified_image.algorithm_version == Some(version))
+    }
+
+    fn constants(&self) -> Constants {
+        // Computed in f32, so that the defaults are exactly the same as the previously hardcoded values
+        let k1 = self.k1 as f32 * self.dynamic_range as f32;
+        let k2 = self.k2 as f32 * self.dynamic_range as f32;
+        Constants { c1: k1 * k1, c2: k2 * k2 }
+    }
+
+    /// Make [`Dssim::compare_images()`] look for a translation between the images, up to `max_shift` pixels in each direction,
+    /// and compare only the aligned overlapping area. The offset is found with sub-pixel precision.
+    ///
+    /// This hides small shifts that are invisible to people, but would otherwise make a large difference in the score.
+    /// The search is done on the lightness of the image, from the smallest scale to the largest.
+    ///
+    /// `0` disables the alignment. This is the default.
+    pub fn set_alignment(&mut self, max_shift: usize) {
+        self.max_alignment_shift = max_shift;
+    }
+
     /// Create image from an array of RGBA pixels (sRGB, non-premultiplied, alpha last).
     ///
     /// If you have a slice of `u8`, then see `rgb` crate's `as_rgba()`.
-    #[must_use]
-    pub fn create_image_rgba(&self, bitmap: &[RGBA<u8>], width: usize, height: usize) -> Option<DssimImage<f32>> {
-        if width * height < bitmap.len() {
-            return None;
+    pub fn create_image_rgba(&self, bitmap: &[RGBA<u8>], width: usize, height: usize) -> Result<DssimImage<f32>, Error> {
+        check_buffer_len(bitmap.len(), width, height)?;
+        let mut pixels = bitmap.to_rgbaplu();
+        if self.hdr {
+            sdr_to_hdr(&mut pixels);
         }
-        let img = ImgVec::new(bitmap.to_rgbaplu(), width, height);
-        self.create_image(&img)
+        self.create_image(&ImgVec::new(pixels, width, height))
     }
 
     /// Create image from an array of packed RGB pixels (sRGB).
     ///
     /// If you have a slice of `u8`, then see `rgb` crate's `as_rgb()`.
-    #[must_use]
-    pub fn create_image_rgb(&self, bitmap: &[RGB<u8>], width: usize, height: usize) -> Option<DssimImage<f32>> {
-        if width * height < bitmap.len() {
-            return None;
+    pub fn create_image_rgb(&self, bitmap: &[RGB<u8>], width: usize, height: usize) -> Result<DssimImage<f32>, Error> {
+        check_buffer_len(bitmap.len(), width, height)?;
+        let mut pixels = bitmap.to_rgblu();
+        if self.hdr {
+            for px in &mut pixels {
+                *px = px.map(|c| c * SDR_WHITE);
+            }
         }
-        let img = ImgVec::new(bitmap.to_rgblu(), width, height);
-        self.create_image(&img)
+        self.create_image(&ImgVec::new(pixels, width, height))
+    }
+
+    /// Create image from an array of RGB pixels encoded with an HDR transfer function, using the low `bits` of every value (e.g. 10 for HDR10).
+    ///
+    /// The primaries are set with [`Dssim::set_color_space()`], which should usually be [`ColorSpace::Rec2020`]. HLG is displayed with 1000 cd/m² peak brightness.
+    ///
+    /// The image is compared in the HDR mode, even if [`Dssim::set_hdr()`] hasn't been enabled, so it can only be compared with other HDR images.
+    ///
+    /// Fails if `bits` isn't in 8..=16.
+    pub fn create_image_hdr(&self, bitmap: &[RGB<u16>], width: usize, height: usize, bits: u8, transfer: HdrTransfer) -> Result<DssimImage<f32>, Error> {
+        check_buffer_len(bitmap.len(), width, height)?;
+        if !(8..=16).contains(&bits) {
+            return Err(Error::InvalidParameter("HDR bit depth must be between 8 and 16"));
+        }
+        let max = f32::from(((1u32 << bits) - 1) as u16);
+        let luma = self.color_space.to_xyz_matrix()[1];
+        let pixels = bitmap[..width * height].par_iter().map(|px| {
+            let px = px.map(|c| f32::from(c) / max);
+            match transfer {
+                HdrTransfer::Pq => px.map(pq_decode),
+                HdrTransfer::Hlg => hlg_decode(px, luma),
+            }
+        }).collect();
+        self.create_image_with(&ImgVec::new(pixels, width, height), true)
     }
 
     /// The input image is defined using the `imgref` crate, and the pixel type can be:
@@ -202,32 +859,109 @@ impl Dssim {
     /// `[RGBA<u8>]`, `[RGBA<u16>]`, `[RGB<u8>]`, or `RGB<u16>`. See `lib.rs` for example how it's done.
     ///
     /// You can implement `ToLABBitmap` and `Downsample` traits on your own image type.
-    pub fn create_image<InBitmap, OutBitmap>(&self, src_img: &InBitmap) -> Option<DssimImage<f32>>
+    ///
+    /// Fails if the image is empty, or is too small for the [`Algorithm`].
+    pub fn create_image<InBitmap, OutBitmap>(&self, src_img: &InBitmap) -> Result<DssimImage<f32>, Error>
+    where
+        InBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
+        OutBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
+    {
+        self.create_image_with(src_img, self.hdr)
+    }
+
+    fn create_image_with<InBitmap, OutBitmap>(&self, src_img: &InBitmap, hdr: bool) -> Result<DssimImage<f32>, Error>
     where
         InBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
         OutBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
     {
+        if hdr && (self.algorithm != Algorithm::Dssim || self.pixel_metrics || self.color_model != ColorModel::Lab) {
+            return Err(Error::InvalidParameter("HDR images support only the DSSIM algorithm, without pixel metrics and other color models"));
+        }
+        let separate_alpha = matches!(self.alpha_mode, AlphaMode::Separate { .. });
+        if separate_alpha && self.algorithm != Algorithm::Dssim {
+            return Err(Error::InvalidParameter("alpha can be compared separately only by the DSSIM algorithm"));
+        }
+        let composited = self.alpha_mode.background(if hdr { SDR_WHITE } else { 1. }).and_then(|bg| src_img.composite(&bg));
+        let mut image = match &composited {
+            Some(composited) => self.create_opaque_image(composited, hdr)?,
+            None => self.create_opaque_image(src_img, hdr)?,
+        };
+        if separate_alpha {
+            let alpha = src_img.to_alpha().unwrap_or_else(|| ImgVec::new(vec![1.; image.width() * image.height()], image.width(), image.height()));
+            image.alpha = Some(alpha_scales(alpha, &image)?);
+        }
+        image.alpha_mode = self.alpha_mode;
+        image.algorithm_version = self.image_algorithm_version(hdr);
+        Ok(image)
+    }
+
+    /// Image with transparency already flattened, or with the default pattern applied by `ToLABBitmap`
+    fn create_opaque_image<InBitmap, OutBitmap>(&self, src_img: &InBitmap, hdr: bool) -> Result<DssimImage<f32>, Error>
+    where
+        InBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
+        OutBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
+    {
+        let pixels = if self.pixel_metrics {
+            Some(src_img.to_linear_rgb().ok_or(Error::InvalidParameter("the image type doesn't support pixel metrics"))?)
+        } else {
+            None
+        };
+        if self.algorithm != Algorithm::Dssim {
+            let luma = src_img.to_luma().ok_or(Error::InvalidParameter("the image type doesn't support luma needed for SSIM"))?;
+            let scale = classic::luma_scales(luma, self.algorithm)?;
+            return Ok(DssimImage { scale, weight_mask: None, blur_kernel: classic::KERNEL, algorithm: self.algorithm, pixels, color_space: self.color_space, color_model: self.color_model.clone(), chroma_blur: 0, hdr, alpha_mode: AlphaMode::Pattern, alpha: None, downsample_filter: DownsampleFilter::default(), small_images: false, algorithm_version: None });
+        }
         let num_scales = self.scale_weights.len();
         let mut scale = Vec::with_capacity(num_scales);
-        Self::make_scales_recursive(num_scales, MaybeArc::Borrowed(src_img), &mut scale);
+        let settings = ScaleSettings {
+            blur_kernel: self.blur_kernel,
+            color_space: self.color_space,
+            color_model: self.color_model.clone(),
+            hdr,
+            chroma_blur: self.chroma_blur_passes(),
+            luma_only: self.channel_weights[1..] == [0., 0.],
+            downsample_filter: self.downsample_filter,
+            small_images: self.small_images,
+        };
+        Self::make_scales_recursive(num_scales, MaybeArc::Borrowed(src_img), &mut scale, settings)?;
         scale.reverse(); // depth-first made smallest scales first
+        if self.small_images {
+            // As many scales as fit in the smaller side of the image. Scales smaller than 8 pixels only for images that small.
+            let fit = scale.iter().take_while(|s| s.chan[0].width.min(s.chan[0].height) >= SMALL_SCALE_SIZE).count();
+            scale.truncate(fit.max(1));
+        }
 
-        Some(DssimImage { scale })
+        Ok(DssimImage { scale, weight_mask: None, blur_kernel: self.blur_kernel, algorithm: Algorithm::Dssim, pixels, color_space: self.color_space, color_model: self.color_model.clone(), chroma_blur: self.chroma_blur_passes(), hdr, alpha_mode: AlphaMode::Pattern, alpha: None, downsample_filter: self.downsample_filter, small_images: self.small_images, algorithm_version: None })
     }
 
     #[inline(never)]
-    fn make_scales_recursive<InBitmap, OutBitmap>(scales_left: usize, image: MaybeArc<'_, InBitmap>, scales: &mut Vec<DssimChanScale<f32>>)
+    fn make_scales_recursive<InBitmap, OutBitmap>(scales_left: usize, image: MaybeArc<'_, InBitmap>, scales: &mut Vec<DssimChanScale<f32>>, settings: ScaleSettings) -> Result<(), Error>
     where
         InBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
         OutBitmap: ToLABBitmap + Send + Sync + Downsample<Output = OutBitmap>,
     {
         // Run to_lab and next downsampling in parallel
-        let (chan, _) = rayon::join({
+        let (chan, down) = rayon::join({
             let image = image.clone();
+            let settings = settings.clone();
             move || {
-                let lab = image.to_lab();
+                let ScaleSettings { blur_kernel, color_space, color_model, hdr, chroma_blur, luma_only, small_images, .. } = settings;
+                let mut lab = if hdr {
+                    image.to_ictcp(color_space).ok_or(Error::InvalidParameter("the image type doesn't support HDR"))?
+                } else {
+                    image.to_color_model(color_space, color_model).ok_or(Error::InvalidParameter("the image type supports only the sRGB color space and L*a*b*"))?
+                };
                 drop(image); // Free larger RGB image ASAP
-                DssimChanScale {
+                if luma_only {
+                    lab.truncate(1);
+                }
+                let (width, height) = lab.first().map(|l| (l.width(), l.height())).ok_or(Error::ZeroDimensions)?;
+                if width == 0 || height == 0 {
+                    return Err(Error::ZeroDimensions);
+                }
+                debug_assert!(lab.iter().all(|l| l.width() == width && l.height() == height));
+                let blur_kernel = blur_kernel.for_scale(small_images, width, height);
+                Ok(DssimChanScale {
                     chan: lab.into_par_iter().with_max_len(1).enumerate().map(|(n,l)| {
                         let w = l.width();
                         let h = l.height();
@@ -235,71 +969,431 @@ impl Dssim {
 
                         let pixels = w * h;
                         let mut tmp = Vec::with_capacity(pixels);
-                        ch.preprocess(&mut tmp.spare_capacity_mut()[..pixels]);
+                        ch.preprocess(&mut tmp.spare_capacity_mut()[..pixels], blur_kernel, chroma_blur);
                         ch
                     }).collect(),
-                }
+                })
             }
         }, {
             let scales = &mut *scales;
             move || {
-                if scales_left > 0 {
-                    let down = image.downsample();
+                if scales_left > 1 {
+                    let down = image.downsample_with(settings.downsample_filter);
                     drop(image);
                     if let Some(downsampled) = down {
-                        Self::make_scales_recursive(scales_left - 1, MaybeArc::Owned(Arc::new(downsampled)), scales);
+                        return Self::make_scales_recursive(scales_left - 1, MaybeArc::Owned(Arc::new(downsampled)), scales, settings);
                     }
                 }
+                Ok(())
             }
         });
-        scales.push(chan);
+        down?;
+        scales.push(chan?);
+        Ok(())
     }
 
     /// Compare original with another image. See `create_image`
     ///
     /// The `SsimMap`s are returned only if you've enabled them first.
     ///
+    /// If the original image has a weight mask set, it's used for pooling the scores.
+    ///
     /// `Val` is a fancy wrapper for `f64`
-    pub fn compare<M: Borrow<DssimImage<f32>>>(&self, original_image: &DssimImage<f32>, modified_image: M) -> (Val, Vec<SsimMap>) {
-        self.compare_inner(original_image, modified_image.borrow())
+    ///
+    /// Fails if the images have different sizes, or have been created with incompatible settings.
+    pub fn compare<M: Borrow<DssimImage<f32>>>(&self, original_image: &DssimImage<f32>, modified_image: M) -> Result<(Val, Vec<SsimMap>), Error> {
+        let modified_image = modified_image.borrow();
+        check_compatible(original_image, modified_image)?;
+        let report = self.compare_inner(original_image, modified_image, false);
+        Ok((report.dssim, report.scales.into_iter().filter_map(|s| s.ssim_map).collect()))
+    }
+
+    /// Compare original with another image, and get scores of every scale and channel.
+    ///
+    /// This is slower than [`Dssim::compare()`], because lightness and chroma are additionally scored separately.
+    /// It also computes [`PixelMetrics`] if they're enabled.
+    pub fn compare_report<M: Borrow<DssimImage<f32>>>(&self, original_image: &DssimImage<f32>, modified_image: M) -> Result<ComparisonReport, Error> {
+        let modified_image = modified_image.borrow();
+        check_compatible(original_image, modified_image)?;
+        let (mut report, metrics) = rayon::join(
+            || self.compare_inner(original_image, modified_image, true),
+            || match (&original_image.pixels, &modified_image.pixels) {
+                (Some(o), Some(m)) if self.pixel_metrics => Some(pixel_metrics(o.as_ref(), m.as_ref(), original_image.color_space, modified_image.color_space)),
+                _ => None,
+            });
+        report.pixel_metrics = metrics;
+        Ok(report)
+    }
+
+    /// Check whether DSSIM of the images is at most `max_dssim`, stopping early when the outcome is certain.
+    ///
+    /// Scales are compared starting from the smallest one, which is the fastest. The comparison stops as soon as the remaining scales
+    /// can't change the outcome, even if they turned out to be identical or completely different.
+    /// Clearly different images usually don't need the full-resolution scale to be compared.
+    /// When all scales are compared, the score is the same as from [`Dssim::compare()`].
+    ///
+    /// The standard SSIM algorithms are always compared in full.
+    ///
+    /// Fails if the threshold is negative or NaN, or for the same reasons as [`Dssim::compare()`].
+    pub fn compare_with_threshold<M: Borrow<DssimImage<f32>>>(&self, original_image: &DssimImage<f32>, modified_image: M, max_dssim: f64) -> Result<ThresholdResult, Error> {
+        let modified_image = modified_image.borrow();
+        check_compatible(original_image, modified_image)?;
+        if max_dssim.is_nan() || max_dssim < 0. {
+            return Err(Error::InvalidParameter("DSSIM threshold must be non-negative"));
+        }
+        if original_image.algorithm != Algorithm::Dssim {
+            let dssim = self.compare_classic(original_image, modified_image).dssim;
+            return Ok(ThresholdResult { passed: dssim <= max_dssim, min_dssim: dssim, max_dssim: dssim, scales_compared: original_image.scale.len() });
+        }
+
+        let scale_weights = self.weights_for_scales(original_image.scale.len(), original_image.small_images);
+        let weight_sum: f64 = scale_weights.iter().sum();
+        let min_ssim = 1. / (1. + max_dssim);
+        let c = self.constants();
+        let channel_weights = self.channel_weights();
+        let mut scores = Vec::with_capacity(scale_weights.len());
+        let (mut best, mut worst) = (1., -1.);
+        for n in (0..scale_weights.len()).rev() {
+            let weights = self.pooling_weights(original_image, modified_image, n, c);
+            let weights = weights.as_deref();
+            let (ssim_map, ..) = self.scale_ssim_map(original_image, modified_image, n, c, channel_weights, weights);
+            scores.push(original_image.scale_pooling(self.pooling, n).pool(ssim_map.as_ref(), n, weights));
+
+            // Summed from the largest scale, the same way as in compare()
+            let ssim_sum = scores.iter().rev().zip(&scale_weights[n..]).fold(0., |sum, (&ssim, &weight)| ssim.mul_add(weight, sum));
+            // Pooled SSIM of the remaining scales is in -1..=1 range
+            let remaining: f64 = scale_weights[..n].iter().sum();
+            best = (ssim_sum + remaining) / weight_sum;
+            worst = (ssim_sum - remaining) / weight_sum;
+            if best < min_ssim || worst >= min_ssim {
+                break;
+            }
+        }
+        Ok(ThresholdResult {
+            passed: worst >= min_ssim,
+            min_dssim: to_dssim(best).into(),
+            max_dssim: to_dssim(worst).into(),
+            scales_compared: scores.len(),
+        })
+    }
+
+    /// Create and compare images in one go, cropping or resampling them if they have different sizes.
+    /// See [`Dssim::set_size_policy()`].
+    ///
+    /// Pixels are the same as for [`Dssim::create_image()`]. The report says what has been done to the images.
+    ///
+    /// Fails if the sizes differ and the policy doesn't allow it, or if the images don't overlap.
+    pub fn compare_images<T>(&self, original: ImgRef<'_, T>, modified: ImgRef<'_, T>) -> Result<ComparisonReport, Error>
+    where
+        T: MixAdd + Send + Sync,
+        for<'a> ImgRef<'a, T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+        ImgVec<T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+    {
+        self.compare_images_inner(original, None, modified, true)
+    }
+
+    /// Like [`Dssim::compare_images()`], but for comparing the same original with many images.
+    ///
+    /// `original_image` must have been created from `original` with [`Dssim::create_image()`]. It's used as-is unless the original has to be cropped.
+    /// Like [`Dssim::compare()`], lightness and chroma aren't scored separately, and pixel metrics aren't computed.
+    pub fn compare_images_with_original<T>(&self, original: ImgRef<'_, T>, original_image: &DssimImage<f32>, modified: ImgRef<'_, T>) -> Result<ComparisonReport, Error>
+    where
+        T: MixAdd + Send + Sync,
+        for<'a> ImgRef<'a, T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+        ImgVec<T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+    {
+        if original_image.width() != original.width() || original_image.height() != original.height() {
+            return Err(Error::SizeMismatch { original: (original.width(), original.height()), modified: (original_image.width(), original_image.height()) });
+        }
+        self.compare_images_inner(original, Some(original_image), modified, false)
+    }
+
+    fn compare_images_inner<T>(&self, original: ImgRef<'_, T>, original_image: Option<&DssimImage<f32>>, modified: ImgRef<'_, T>, per_channel: bool) -> Result<ComparisonReport, Error>
+    where
+        T: MixAdd + Send + Sync,
+        for<'a> ImgRef<'a, T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+        ImgVec<T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+    {
+        let (ow, oh) = (original.width(), original.height());
+        let (mw, mh) = (modified.width(), modified.height());
+        let crop_at = |x: isize, y: isize| {
+            let left = x.max(0) as usize;
+            let top = y.max(0) as usize;
+            let right = (x.saturating_add_unsigned(mw).max(0) as usize).min(ow);
+            let bottom = (y.saturating_add_unsigned(mh).max(0) as usize).min(oh);
+            if right <= left || bottom <= top {
+                return Err(Error::InvalidParameter("images don't overlap"));
+            }
+            let (width, height) = (right - left, bottom - top);
+            let original = original.sub_image(left, top, width, height);
+            let modified = modified.sub_image((left as isize - x) as usize, (top as isize - y) as usize, width, height);
+            Ok((original, modified, SizeAdjustment::Cropped { left, top, width, height }))
+        };
+
+        let resampled;
+        let (original, modified, size_adjustment) = match self.size_policy {
+            SizePolicy::CropAt { x, y } if x != 0 || y != 0 => crop_at(x, y)?,
+            _ if ow == mw && oh == mh => (original, modified, SizeAdjustment::None),
+            SizePolicy::Exact => return Err(Error::SizeMismatch { original: (ow, oh), modified: (mw, mh) }),
+            SizePolicy::CropToIntersection | SizePolicy::CropAt { .. } => crop_at(0, 0)?,
+            SizePolicy::Resample => {
+                if ow == 0 || oh == 0 || mw == 0 || mh == 0 {
+                    return Err(Error::ZeroDimensions);
+                }
+                resampled = resample(modified, ow, oh);
+                (original, resampled.as_ref(), SizeAdjustment::Resampled { width: mw, height: mh })
+            },
+        };
+        // Cropping only makes the original smaller
+        let original_image = original_image.filter(|_| original.width() == ow && original.height() == oh);
+
+        let mut report = if self.max_alignment_shift == 0 {
+            self.compare_created(original, original_image, modified, per_channel)?
+        } else {
+            self.compare_aligned(original, original_image, modified, size_adjustment, per_channel)?
+        };
+        report.size_adjustment = size_adjustment;
+        Ok(report)
+    }
+
+    fn compare_aligned<T>(&self, original: ImgRef<'_, T>, original_image: Option<&DssimImage<f32>>, modified: ImgRef<'_, T>, size_adjustment: SizeAdjustment, per_channel: bool) -> Result<ComparisonReport, Error>
+    where
+        T: MixAdd + Send + Sync,
+        for<'a> ImgRef<'a, T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+        ImgVec<T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+    {
+        let (original_planes, modified_planes) = rayon::join(|| self.lightness_planes(&original), || self.lightness_planes(&modified));
+        let original_planes: Vec<_> = original_planes.iter().map(|p| p.as_ref()).collect();
+        let modified_planes: Vec<_> = modified_planes.iter().map(|p| p.as_ref()).collect();
+        let (dx, dy) = align::find_offset(&original_planes, &modified_planes, self.max_alignment_shift);
+        let (width, height) = (original.width(), original.height());
+        // The original's pixels that have a matching pixel in the modified image
+        let overlap = |d: f64, orig_len: usize, mod_len: usize| {
+            let start = (-d).ceil().max(0.) as usize;
+            let end = ((mod_len as f64 - 1. - d).floor() + 1.).clamp(0., orig_len as f64) as usize;
+            (start, end.saturating_sub(start))
+        };
+        let (left, width) = overlap(dx, width, modified.width());
+        let (top, height) = overlap(dy, height, modified.height());
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidParameter("images don't overlap"));
+        }
+
+        let mut report = if dx == 0. && dy == 0. {
+            self.compare_created(original, original_image, modified, per_channel)?
+        } else {
+            let shifted = translate(modified, left as f64 + dx, top as f64 + dy, width, height);
+            let original = original.sub_image(left, top, width, height);
+            self.compare_created(original, None, shifted.as_ref(), per_channel)?
+        };
+        let (crop_left, crop_top) = match size_adjustment {
+            SizeAdjustment::Cropped { left, top, .. } => (left, top),
+            _ => (0, 0),
+        };
+        report.alignment = Some(Alignment { dx, dy, left: crop_left + left, top: crop_top + top, width, height });
+        Ok(report)
+    }
+
+    /// Create images that haven't been created yet, and compare them
+    fn compare_created<T>(&self, original: ImgRef<'_, T>, original_image: Option<&DssimImage<f32>>, modified: ImgRef<'_, T>, per_channel: bool) -> Result<ComparisonReport, Error>
+    where
+        T: MixAdd + Send + Sync,
+        for<'a> ImgRef<'a, T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+        ImgVec<T>: ToLABBitmap + Downsample<Output = ImgVec<T>>,
+    {
+        let created;
+        let original_image = match original_image {
+            Some(image) => image,
+            None => {
+                created = self.create_image(&original)?;
+                &created
+            },
+        };
+        let modified_image = self.create_image(&modified)?;
+        if per_channel {
+            self.compare_report(original_image, modified_image)
+        } else {
+            check_compatible(original_image, &modified_image)?;
+            Ok(self.compare_inner(original_image, &modified_image, false))
+        }
+    }
+
+    /// Lightness of every scale, only for finding the alignment
+    fn lightness_planes<B>(&self, image: &B) -> Vec<ImgVec<f32>>
+    where B: ToLABBitmap {
+        let Some(full) = image.to_lab().into_iter().next() else { return Vec::new() };
+        let mut planes = vec![full];
+        while planes.len() < self.scale_weights.len() {
+            match planes[planes.len() - 1].downsample_with(self.downsample_filter) {
+                Some(down) => planes.push(down),
+                None => break,
+            }
+        }
+        planes
+    }
+
+    /// Same as the number of scales that `create_image` will make for the whole image
+    fn tiled_scale_sizes(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let mut scale_sizes = vec![(width, height)];
+        let (mut w, mut h) = (width, height);
+        while scale_sizes.len() < self.scale_weights.len() && w >= 8 && h >= 8 {
+            (w, h) = if self.downsample_filter == DownsampleFilter::BoxTruncating { (w / 2, h / 2) } else { (w.div_ceil(2), h.div_ceil(2)) };
+            if self.small_images && w.min(h) < SMALL_SCALE_SIZE {
+                break;
+            }
+            scale_sizes.push((w, h));
+        }
+        scale_sizes
+    }
+
+    /// Upper bound of memory used by [`Dssim::compare_tiled()`] for a band of `rows` rows.
+    ///
+    /// Both images of a band are created at the same time. For every pixel of every scale, each has the RGBA pixels,
+    /// RGB pixels if they're flattened over a background, buffers of the resampling filters, the three converted planes,
+    /// and the blurred image, blurred square, and a blur buffer of every channel. The first scale may also have
+    /// the rows of flattened pixels before they're joined, and pixels for the metrics. Comparing the scales afterwards needs less.
+    fn tiled_band_bytes(&self, scale_sizes: &[(usize, usize)], rows: usize) -> usize {
+        let channels = if self.channel_weights[1..] == [0., 0.] { 1 } else { 3 };
+        let flattened = self.alpha_mode.background(1.).is_some();
+        let rgb = size_of::<RGBAPLU>() + if flattened { size_of::<RGBLU>() } else { 0 };
+        let resample_tmp = if matches!(self.downsample_filter, DownsampleFilter::Box | DownsampleFilter::BoxTruncating) { 0 } else { rgb.div_ceil(2) };
+        let per_pixel = rgb + resample_tmp + (3 + 3 * channels) * size_of::<f32>();
+        let scales = scale_sizes.iter().enumerate().fold(0usize, |sum, (n, &(w, _))| {
+            sum.saturating_add(w.saturating_mul(rows.div_ceil(1 << n)).saturating_mul(per_pixel))
+        });
+        let mut first_scale_row = 0;
+        if flattened {
+            first_scale_row += scale_sizes[0].0.saturating_mul(size_of::<RGBLU>()) + size_of::<(usize, &[RGBAPLU])>() + size_of::<Vec<RGBLU>>();
+        }
+        if self.pixel_metrics {
+            first_scale_row += scale_sizes[0].0.saturating_mul(size_of::<RGBLU>());
+        }
+        scales.saturating_add(rows.saturating_mul(first_scale_row)).saturating_mul(2)
+    }
+
+    /// Compare images that are too large for [`Dssim::create_image()`].
+    ///
+    /// The images are converted and compared in horizontal bands. The bands overlap enough to get the same per-pixel scores
+    /// as when comparing whole images. `max_memory` is an approximate limit (in bytes) of memory used for the bands,
+    /// not counting the input images. The pixels are sRGB, like in [`Dssim::create_image_rgba()`].
+    ///
+    /// The score is the same as from [`Dssim::compare()`] within about 10⁻⁵, because the SSIM values are pooled from a fine-grained histogram.
+    ///
+    /// Fails if the images have different sizes, the memory limit is too small for the image width, [`Pooling::WorstBlock`] or [`Pooling::InformationWeighted`] is used,
+    /// alpha is compared separately, or the [`Algorithm`] isn't DSSIM.
+    pub fn compare_tiled<P>(&self, original: ImgRef<'_, P>, modified: ImgRef<'_, P>, max_memory: usize) -> Result<Val, Error>
+    where
+        P: Copy + Send + Sync,
+        [P]: ToRGBAPLU,
+    {
+        let (width, height) = (original.width(), original.height());
+        if width != modified.width() || height != modified.height() {
+            return Err(Error::SizeMismatch { original: (width, height), modified: (modified.width(), modified.height()) });
+        }
+        check_buffer_len(width * height, width, height)?;
+        if matches!(self.pooling, Pooling::WorstBlock(_) | Pooling::InformationWeighted) {
+            return Err(Error::InvalidParameter("worst block and information-weighted pooling are not supported in tiled comparison"));
+        }
+        if self.algorithm != Algorithm::Dssim {
+            return Err(Error::InvalidParameter("tiled comparison supports only the DSSIM algorithm"));
+        }
+        if matches!(self.alpha_mode, AlphaMode::Separate { .. }) {
+            return Err(Error::InvalidParameter("tiled comparison can't compare alpha separately"));
+        }
+
+        let scale_sizes = self.tiled_scale_sizes(width, height);
+        let num_scales = scale_sizes.len();
+        // Bands start at multiples of this, so that downsampling gives the same pixels as for the whole image
+        let align = 1 << (num_scales - 1);
+        // Enough for the blurs (including chroma pre-blur) and downsampling filters at every scale, and tall enough for downsampling
+        let margin = ((1 + usize::from(self.chroma_blur_passes())) * self.blur_kernel.radius() + self.downsample_filter.taps().len() - 2).max(8) * align;
+        // Most rows that fit in the memory limit
+        let (mut max_rows, mut too_many_rows) = (0, height + 1);
+        while max_rows + 1 < too_many_rows {
+            let rows = usize::midpoint(max_rows, too_many_rows);
+            if self.tiled_band_bytes(&scale_sizes, rows) <= max_memory { max_rows = rows } else { too_many_rows = rows }
+        }
+        let core_rows = if max_rows >= height { height } else { max_rows.saturating_sub(2 * margin) / align * align };
+        if core_rows == 0 {
+            return Err(Error::InvalidParameter("memory limit is too small for the image width"));
+        }
+
+        let band_image = |img: ImgRef<'_, P>, top: usize, height: usize| {
+            let mut pixels = Vec::with_capacity(width * height);
+            for row in img.sub_image(0, top, width, height).rows() {
+                pixels.extend(row.to_rgbaplu());
+            }
+            if self.hdr {
+                sdr_to_hdr(&mut pixels);
+            }
+            self.create_image(&ImageBand { pixels: ImgVec::new(pixels, width, height), top })
+        };
+
+        let c = self.constants();
+        let channel_weights = self.channel_weights();
+        let mut histograms: Vec<_> = (0..num_scales).map(|_| Histogram::new()).collect();
+        let mut core_start = 0;
+        while core_start < height {
+            let core_end = (core_start + core_rows).min(height);
+            let band_start = core_start.saturating_sub(margin);
+            let band_end = (core_end + margin).min(height);
+            let (o, m) = rayon::join(
+                || band_image(original, band_start, band_end - band_start),
+                || band_image(modified, band_start, band_end - band_start));
+            let (o, m) = (o?, m?);
+            debug_assert_eq!(num_scales, o.scale.len());
+
+            for (n, ((os, ms), hist)) in o.scale.iter().zip(&m.scale).zip(&mut histograms).enumerate() {
+                let (map, _) = Self::ssim_map(os, ms, c, o.scale_kernel(n), channel_weights);
+                let top = (core_start - band_start) >> n;
+                let bottom = if core_end == height { map.height() } else { (core_end - band_start) >> n };
+                hist.add(&map.buf()[top * map.width()..bottom * map.width()]);
+            }
+            core_start = core_end;
+        }
+
+        let mut ssim_sum = 0.0;
+        let mut weight_sum = 0.0;
+        for (n, ((hist, &weight), &(w, h))) in histograms.iter().zip(&self.weights_for_scales(num_scales, self.small_images)).zip(&scale_sizes).enumerate() {
+            ssim_sum = hist.pool(self.pooling.for_scale(self.small_images, w, h), n).mul_add(weight, ssim_sum);
+            weight_sum += weight;
+        }
+        Ok(to_dssim(ssim_sum / weight_sum).into())
     }
 
     #[inline(never)]
-    fn compare_inner(&self, original_image: &DssimImage<f32>, modified_image: &DssimImage<f32>) -> (Val, Vec<SsimMap>) {
+    fn compare_inner(&self, original_image: &DssimImage<f32>, modified_image: &DssimImage<f32>, per_channel: bool) -> ComparisonReport {
+        if original_image.algorithm != Algorithm::Dssim {
+            return self.compare_classic(original_image, modified_image);
+        }
         let scaled_images_iter = modified_image.scale.iter().zip(original_image.scale.iter());
-        let combined_iter = self.scale_weights.iter().copied().zip(scaled_images_iter).enumerate();
+        let scale_weights = self.weights_for_scales(original_image.scale.len(), original_image.small_images);
+        let combined: Vec<_> = scale_weights.iter().copied().zip(scaled_images_iter).enumerate().collect();
 
-        let res: Vec<_> = combined_iter.par_bridge().map(|(n, (weight, (modified_image_scale, original_image_scale)))| {
+        let c = self.constants();
+        let channel_weights = self.channel_weights();
+        let scales: Vec<_> = combined.into_par_iter().map(|(n, (weight, (modified_image_scale, original_image_scale)))| {
             let scale_width = original_image_scale.chan[0].width;
             let scale_height = original_image_scale.chan[0].height;
-            let pixels = scale_width * scale_height;
-
-            let ssim_map = match original_image_scale.chan.len() {
-                3 => {
-                    // Compute the per-channel cross-blur (img1·img2 then blur) for L, a, b
-                    // in parallel — three independent blurs over disjoint memory.
-                    // Each channel gets its own tmp buffer.
-                    let img1_img2_blur: Vec<Vec<f32>> = (0..3usize).into_par_iter().map(|c| {
-                        let mut tmp_buf: Vec<f32> = Vec::with_capacity(pixels);
-                        let tmp = &mut tmp_buf.spare_capacity_mut()[..pixels];
-                        original_image_scale.chan[c]
-                            .img1_img2_blur(&modified_image_scale.chan[c], tmp)
-                    }).collect();
-                    Self::compare_scale_3ch(original_image_scale, modified_image_scale, &img1_img2_blur)
-                },
-                1 => {
-                    let mut tmp_buf: Vec<f32> = Vec::with_capacity(pixels);
-                    let tmp = &mut tmp_buf.spare_capacity_mut()[..pixels];
-                    let img1_img2_blur = original_image_scale.chan[0].img1_img2_blur(&modified_image_scale.chan[0], tmp);
-                    Self::compare_scale(&original_image_scale.chan[0], &modified_image_scale.chan[0], &img1_img2_blur)
-                },
-                _ => panic!(),
+            let weights = self.pooling_weights(original_image, modified_image, n, c);
+            let weights = weights.as_deref();
+
+            let pooling = original_image.scale_pooling(self.pooling, n);
+            let (ssim_map, img1_img2_blur, alpha_ssim) = self.scale_ssim_map(original_image, modified_image, n, c, channel_weights, weights);
+            let (luma_ssim, chroma_ssim) = if per_channel && original_image_scale.chan.len() == 3 {
+                let (luma_map, chroma_map) = Self::compare_scale_luma_chroma(original_image_scale, modified_image_scale, &img1_img2_blur, c, channel_weights);
+                (Some(pooling.pool(luma_map.as_ref(), n, weights)), Some(pooling.pool(chroma_map.as_ref(), n, weights)))
+            } else {
+                (None, None)
+            };
+
+            let components = if per_channel && self.save_components {
+                Some(Self::compare_scale_components(original_image_scale, modified_image_scale, &img1_img2_blur, weights, c, channel_weights))
+            } else {
+                None
             };
 
-            let sum = ssim_map.pixels().fold(0., |sum, i| sum + f64::from(i));
-            let len = (ssim_map.width()*ssim_map.height()) as f64;
-            let avg = (sum / len).max(0.0).powf((0.5_f64).powf(n as f64));
-            let score = 1.0 - (ssim_map.pixels().fold(0., |sum, i| sum + (avg - f64::from(i)).abs()) / len);
+            let score = pooling.pool(ssim_map.as_ref(), n, weights);
 
             let map = if self.save_maps_scales as usize > n {
                 Some(SsimMap {
@@ -309,24 +1403,256 @@ impl Dssim {
             } else {
                 None
             };
-            (score, weight, map)
+            ScaleReport {
+                width: scale_width,
+                height: scale_height,
+                ssim: score,
+                weight,
+                luma_ssim: luma_ssim.unwrap_or(score),
+                chroma_ssim,
+                alpha_ssim,
+                ssim_map: map,
+                components,
+            }
         }).collect();
 
         let mut ssim_sum = 0.0;
         let mut weight_sum = 0.0;
-        let mut ssim_maps = Vec::new();
-        for (score, weight, map) in res {
-            ssim_sum = score.mul_add(weight, ssim_sum);
-            weight_sum += weight;
-            if let Some(m) = map {
-                ssim_maps.push(m);
-            }
+        for s in &scales {
+            ssim_sum = s.ssim.mul_add(s.weight, ssim_sum);
+            weight_sum += s.weight;
+        }
+
+        let dssim = to_dssim(ssim_sum / weight_sum);
+        ComparisonReport {
+            dssim: dssim.into(),
+            ssim: 1. / (1. + dssim),
+            scales,
+            size_adjustment: SizeAdjustment::None,
+            alignment: None,
+            pixel_metrics: None,
+            algorithm_version: self.report_algorithm_version(original_image, modified_image),
+            alpha_mode: original_image.alpha_mode,
+            skipped_scales: self.scale_weights.len().saturating_sub(scale_weights.len()),
+        }
+    }
+
+    /// Weight mask of the original image for the scale `n`, with information content added for [`Pooling::InformationWeighted`]
+    fn pooling_weights<'a>(&self, original_image: &'a DssimImage<f32>, modified_image: &DssimImage<f32>, n: usize, c: Constants) -> Option<Cow<'a, [f32]>> {
+        let weights = original_image.weight_mask.as_ref().map(|m| m[n].buf().as_slice());
+        if self.pooling == Pooling::InformationWeighted {
+            return Some(information_weights(&original_image.scale[n].chan[0], &modified_image.scale[n].chan[0], c.c2, weights).into());
         }
+        weights.map(Cow::Borrowed)
+    }
+
+    /// SSIM map of the scale `n`, with the separately compared alpha blended in, and pooled SSIM of the alpha alone
+    fn scale_ssim_map(&self, original_image: &DssimImage<f32>, modified_image: &DssimImage<f32>, n: usize, c: Constants, channel_weights: [f32; 3], weights: Option<&[f32]>) -> (ImgVec<f32>, Vec<Vec<f32>>, Option<f64>) {
+        let kernel = original_image.scale_kernel(n);
+        let (mut ssim_map, img1_img2_blur) = Self::ssim_map(&original_image.scale[n], &modified_image.scale[n], c, kernel, channel_weights);
+        let alpha_ssim = match (&original_image.alpha, &modified_image.alpha, original_image.alpha_mode) {
+            (Some(o), Some(m), AlphaMode::Separate { weight }) => {
+                let (o, m) = (&o[n], &m[n]);
+                let mut tmp = Vec::with_capacity(o.width * o.height);
+                let img1_img2_blur = o.img1_img2_blur(m, &mut tmp.spare_capacity_mut()[..o.width * o.height], kernel);
+                let alpha_map = Self::compare_scale(o, m, &img1_img2_blur, c);
+                let alpha_weight = (weight / (1. + weight)) as f32;
+                for (px, &a) in ssim_map.buf_mut().iter_mut().zip(alpha_map.buf()) {
+                    *px = (a - *px).mul_add(alpha_weight, *px);
+                }
+                Some(original_image.scale_pooling(self.pooling, n).pool(alpha_map.as_ref(), n, weights))
+            },
+            _ => None,
+        };
+        (ssim_map, img1_img2_blur, alpha_ssim)
+    }
 
-        (to_dssim(ssim_sum / weight_sum).into(), ssim_maps)
+    /// Weights of the first `num_scales` scales. In small-image mode, the last one also gets the weights of the scales that are missing.
+    fn weights_for_scales(&self, num_scales: usize, small_images: bool) -> Vec<f64> {
+        let mut weights = self.scale_weights.clone();
+        let missing: f64 = weights.drain(num_scales.min(weights.len())..).sum();
+        if small_images && let Some(last) = weights.last_mut() {
+            *last += missing;
+        }
+        weights
     }
 
-    /// 3-channel SSIM combine, scalar but unrolled across L/a/b. Reads the three
+    /// Standard SSIM or MS-SSIM of luma. Images have one channel per scale.
+    fn compare_classic(&self, original_image: &DssimImage<f32>, modified_image: &DssimImage<f32>) -> ComparisonReport {
+        let algorithm = original_image.algorithm;
+        let last = original_image.scale.len() - 1;
+        let combined: Vec<_> = original_image.scale.iter().zip(&modified_image.scale).enumerate().collect();
+
+        let c = self.constants();
+        let scales: Vec<_> = combined.into_par_iter().map(|(n, (original_image_scale, modified_image_scale))| {
+            let (ssim_map, img1_img2_blur) = Self::ssim_map(original_image_scale, modified_image_scale, c, original_image.blur_kernel, [1., 0., 0.]);
+            let ssim_map = classic::valid_area(ssim_map.as_ref());
+            let weights = original_image.weight_mask.as_ref().map(|m| classic::valid_area(m[n].as_ref()));
+            let weights = weights.as_ref().map(|w| w.buf().as_slice());
+            let score = Pooling::Mean.pool(ssim_map.as_ref(), n, weights);
+
+            // MS-SSIM uses only contrast and structure of all but the last scale
+            let cs = if n < last {
+                let cs_map = classic::cs_map(&original_image_scale.chan[0], &modified_image_scale.chan[0], &img1_img2_blur[0], c.c2);
+                Pooling::Mean.pool(classic::valid_area(cs_map.as_ref()).as_ref(), n, weights)
+            } else {
+                score
+            };
+
+            let weight = if algorithm == Algorithm::MsSsim { classic::MS_SSIM_WEIGHTS[n] } else { 1. };
+            let report = ScaleReport {
+                width: original_image_scale.chan[0].width,
+                height: original_image_scale.chan[0].height,
+                ssim: score,
+                weight,
+                luma_ssim: score,
+                chroma_ssim: None,
+                alpha_ssim: None,
+                ssim_map: (self.save_maps_scales as usize > n).then_some(SsimMap { map: ssim_map, ssim: score }),
+                components: None,
+            };
+            (report, cs)
+        }).collect();
+
+        // Negative terms are clamped, because they can't have fractional exponents
+        let ssim = scales.iter().fold(1., |product, (s, cs)| product * cs.max(0.).powf(s.weight));
+        ComparisonReport {
+            dssim: to_dssim(ssim).into(),
+            ssim,
+            scales: scales.into_iter().map(|(s, _)| s).collect(),
+            size_adjustment: SizeAdjustment::None,
+            alignment: None,
+            pixel_metrics: None,
+            algorithm_version: self.report_algorithm_version(original_image, modified_image),
+            alpha_mode: original_image.alpha_mode,
+            skipped_scales: 0,
+        }
+    }
+
+    /// SSIM map of a scale, and blurred products of the images' channels that have been computed for it
+    fn ssim_map(original_image_scale: &DssimChanScale<f32>, modified_image_scale: &DssimChanScale<f32>, c: Constants, kernel: BlurKernel, channel_weights: [f32; 3]) -> (ImgVec<f32>, Vec<Vec<f32>>) {
+        let pixels = original_image_scale.chan[0].width * original_image_scale.chan[0].height;
+        match original_image_scale.chan.len() {
+            3 => {
+                // Compute the per-channel cross-blur (img1·img2 then blur) for L, a, b
+                // in parallel — three independent blurs over disjoint memory.
+                // Each channel gets its own tmp buffer.
+                let img1_img2_blur: Vec<Vec<f32>> = (0..3usize).into_par_iter().map(|c| {
+                    let mut tmp_buf: Vec<f32> = Vec::with_capacity(pixels);
+                    let tmp = &mut tmp_buf.spare_capacity_mut()[..pixels];
+                    original_image_scale.chan[c]
+                        .img1_img2_blur(&modified_image_scale.chan[c], tmp, kernel)
+                }).collect();
+                (Self::compare_scale_3ch(original_image_scale, modified_image_scale, &img1_img2_blur, c, channel_weights), img1_img2_blur)
+            },
+            1 => {
+                let mut tmp_buf: Vec<f32> = Vec::with_capacity(pixels);
+                let tmp = &mut tmp_buf.spare_capacity_mut()[..pixels];
+                let img1_img2_blur = original_image_scale.chan[0].img1_img2_blur(&modified_image_scale.chan[0], tmp, kernel);
+                let ssim_map = Self::compare_scale(&original_image_scale.chan[0], &modified_image_scale.chan[0], &img1_img2_blur, c);
+                (ssim_map, vec![img1_img2_blur])
+            },
+            _ => unreachable!("checked in check_compatible"),
+        }
+    }
+
+    /// SSIM of the L channel alone, and of a and b channels together.
+    ///
+    /// It's a separate pass over the data, because it's needed only for `compare_report`.
+    #[inline(never)]
+    fn compare_scale_luma_chroma(
+        original: &DssimChanScale<f32>,
+        modified: &DssimChanScale<f32>,
+        img1_img2_blur: &[Vec<f32>],
+        c: Constants,
+        channel_weights: [f32; 3],
+    ) -> (ImgVec<f32>, ImgVec<f32>) {
+        let width = original.chan[0].width;
+        let height = original.chan[0].height;
+        let pixels = width * height;
+
+        let luma_map = Self::compare_scale(&original.chan[0], &modified.chan[0], &img1_img2_blur[0], c);
+
+        let (o1, o2) = (&original.chan[1], &original.chan[2]);
+        let (m1, m2) = (&modified.chan[1], &modified.chan[2]);
+        let (i12_1, i12_2) = (&img1_img2_blur[1][..pixels], &img1_img2_blur[2][..pixels]);
+
+        let Constants { c1, c2 } = c;
+        // Relative weights of the two chroma channels
+        let chroma_sum = channel_weights[1] + channel_weights[2];
+        let (w1, w2) = if chroma_sum > 0. { (channel_weights[1] / chroma_sum, channel_weights[2] / chroma_sum) } else { (0.5, 0.5) };
+
+        let chroma_map: Vec<f32> = (0..pixels).into_par_iter().with_min_len(1 << 10).map(|i| {
+            let mu1_1 = o1.mu[i]; let mu2_1 = m1.mu[i];
+            let mu1_2 = o2.mu[i]; let mu2_2 = m2.mu[i];
+
+            let mu1_sq  = (mu1_1 * mu1_1).mul_add(w1, mu1_2 * mu1_2 * w2);
+            let mu2_sq  = (mu2_1 * mu2_1).mul_add(w1, mu2_2 * mu2_2 * w2);
+            let mu1_mu2 = (mu1_1 * mu2_1).mul_add(w1, mu1_2 * mu2_2 * w2);
+
+            let sigma1_sq = (o1.img_sq_blur[i] - mu1_1 * mu1_1).mul_add(w1, (o2.img_sq_blur[i] - mu1_2 * mu1_2) * w2);
+            let sigma2_sq = (m1.img_sq_blur[i] - mu2_1 * mu2_1).mul_add(w1, (m2.img_sq_blur[i] - mu2_2 * mu2_2) * w2);
+            let sigma12 = (i12_1[i] - mu1_1 * mu2_1).mul_add(w1, (i12_2[i] - mu1_2 * mu2_2) * w2);
+
+            2.0f32.mul_add(mu1_mu2, c1) * 2.0f32.mul_add(sigma12, c2)
+                / ((mu1_sq + mu2_sq + c1) * (sigma1_sq + sigma2_sq + c2))
+        }).collect();
+
+        (luma_map, ImgVec::new(chroma_map, width, height))
+    }
+
+    /// Luminance, contrast and structure terms computed from the same statistics as the SSIM map
+    /// (channels averaged with their weights), so that their product is the SSIM.
+    #[inline(never)]
+    fn compare_scale_components(
+        original: &DssimChanScale<f32>,
+        modified: &DssimChanScale<f32>,
+        img1_img2_blur: &[Vec<f32>],
+        weights: Option<&[f32]>,
+        c: Constants,
+        channel_weights: [f32; 3],
+    ) -> SsimComponents {
+        let width = original.chan[0].width;
+        let height = original.chan[0].height;
+        let pixels = width * height;
+
+        let Constants { c1, c2 } = c;
+        let c3 = c2 * 0.5;
+        let channel_weights = if original.chan.len() == 3 { channel_weights } else { [1., 0., 0.] };
+
+        let terms: Vec<[f32; 3]> = (0..pixels).into_par_iter().with_min_len(1 << 10).map(|i| {
+            let mut mu1_sq = 0.; let mut mu2_sq = 0.; let mut mu1_mu2 = 0.;
+            let mut sigma1_sq = 0.; let mut sigma2_sq = 0.; let mut sigma12 = 0.;
+            for (((o, m), i12), w) in original.chan.iter().zip(&modified.chan).zip(img1_img2_blur).zip(channel_weights) {
+                let mu1 = o.mu[i]; let mu2 = m.mu[i];
+                mu1_sq += mu1 * mu1 * w;
+                mu2_sq += mu2 * mu2 * w;
+                mu1_mu2 += mu1 * mu2 * w;
+                sigma1_sq += (o.img_sq_blur[i] - mu1 * mu1) * w;
+                sigma2_sq += (m.img_sq_blur[i] - mu2 * mu2) * w;
+                sigma12 += (i12[i] - mu1 * mu2) * w;
+            }
+            let sigma1_sigma2 = (sigma1_sq.max(0.) * sigma2_sq.max(0.)).sqrt();
+
+            [
+                2.0f32.mul_add(mu1_mu2, c1) / (mu1_sq + mu2_sq + c1),
+                2.0f32.mul_add(sigma1_sigma2, c2) / (sigma1_sq + sigma2_sq + c2),
+                (sigma12 + c3) / (sigma1_sigma2 + c3),
+            ]
+        }).collect();
+
+        let term = |t: usize| {
+            let map: Vec<f32> = terms.iter().map(|px| px[t]).collect();
+            let avg = weighed_mean(&map, weights);
+            (avg, ImgVec::new(map, width, height))
+        };
+        let (luminance, luminance_map) = term(0);
+        let (contrast, contrast_map) = term(1);
+        let (structure, structure_map) = term(2);
+        SsimComponents { luminance, contrast, structure, luminance_map, contrast_map, structure_map }
+    }
+
+    /// 3-channel SSIM combine, scalar but unrolled across L/a/b, with channels weighted by `channel_weights` (which sum to 1). Reads the three
     /// channels directly from the per-channel `mu` and `img_sq_blur` Vecs and the
     /// three `img1_img2_blur` Vecs computed earlier in parallel — no LAB struct
     /// interleaving, no zip-iterator overhead, and per-channel slices stay
@@ -336,6 +1662,8 @@ impl Dssim {
         original: &DssimChanScale<f32>,
         modified: &DssimChanScale<f32>,
         img1_img2_blur: &[Vec<f32>],
+        c: Constants,
+        channel_weights: [f32; 3],
     ) -> ImgVec<f32> {
         let width = original.chan[0].width;
         let height = original.chan[0].height;
@@ -360,9 +1688,8 @@ impl Dssim {
         let i12_1 = &img1_img2_blur[1][..pixels];
         let i12_2 = &img1_img2_blur[2][..pixels];
 
-        let c1: f32 = 0.01 * 0.01;
-        let c2: f32 = 0.03 * 0.03;
-        let inv3: f32 = 1.0 / 3.0;
+        let Constants { c1, c2 } = c;
+        let [w0, w1, w2] = channel_weights;
 
         let map_out: Vec<f32> = (0..pixels).into_par_iter().with_min_len(1 << 10).map(|i| {
             let mu1_0 = o0_mu[i]; let mu2_0 = m0_mu[i];
@@ -379,13 +1706,13 @@ impl Dssim {
             let mu1mu2_1 = mu1_1 * mu2_1;
             let mu1mu2_2 = mu1_2 * mu2_2;
 
-            let mu1_sq  = (mu1mu1_0 + mu1mu1_1 + mu1mu1_2) * inv3;
-            let mu2_sq  = (mu2mu2_0 + mu2mu2_1 + mu2mu2_2) * inv3;
-            let mu1_mu2 = (mu1mu2_0 + mu1mu2_1 + mu1mu2_2) * inv3;
+            let mu1_sq  = mu1mu1_0.mul_add(w0, mu1mu1_1.mul_add(w1, mu1mu1_2 * w2));
+            let mu2_sq  = mu2mu2_0.mul_add(w0, mu2mu2_1.mul_add(w1, mu2mu2_2 * w2));
+            let mu1_mu2 = mu1mu2_0.mul_add(w0, mu1mu2_1.mul_add(w1, mu1mu2_2 * w2));
 
-            let sigma1_sq = ((o0_sq[i] - mu1mu1_0) + (o1_sq[i] - mu1mu1_1) + (o2_sq[i] - mu1mu1_2)) * inv3;
-            let sigma2_sq = ((m0_sq[i] - mu2mu2_0) + (m1_sq[i] - mu2mu2_1) + (m2_sq[i] - mu2mu2_2)) * inv3;
-            let sigma12  = ((i12_0[i] - mu1mu2_0) + (i12_1[i] - mu1mu2_1) + (i12_2[i] - mu1mu2_2)) * inv3;
+            let sigma1_sq = (o0_sq[i] - mu1mu1_0).mul_add(w0, (o1_sq[i] - mu1mu1_1).mul_add(w1, (o2_sq[i] - mu1mu1_2) * w2));
+            let sigma2_sq = (m0_sq[i] - mu2mu2_0).mul_add(w0, (m1_sq[i] - mu2mu2_1).mul_add(w1, (m2_sq[i] - mu2mu2_2) * w2));
+            let sigma12  = (i12_0[i] - mu1mu2_0).mul_add(w0, (i12_1[i] - mu1mu2_1).mul_add(w1, (i12_2[i] - mu1mu2_2) * w2));
 
             2.0f32.mul_add(mu1_mu2, c1) * 2.0f32.mul_add(sigma12, c2)
                 / ((mu1_sq + mu2_sq + c1) * (sigma1_sq + sigma2_sq + c2))
@@ -395,19 +1722,18 @@ impl Dssim {
     }
 
     #[inline(never)]
-    fn compare_scale<L>(original: &DssimChan<L>, modified: &DssimChan<L>, img1_img2_blur: &[L]) -> ImgVec<f32>
+    fn compare_scale<L>(original: &DssimChan<L>, modified: &DssimChan<L>, img1_img2_blur: &[L], c: Constants) -> ImgVec<f32>
     where
         L: Send + Sync + Clone + Copy + ops::Mul<Output = L> + ops::Sub<Output = L> + 'static,
         f32: From<L>,
     {
-        assert_eq!(original.width, modified.width);
-        assert_eq!(original.height, modified.height);
+        debug_assert_eq!(original.width, modified.width);
+        debug_assert_eq!(original.height, modified.height);
 
         let width = original.width;
         let height = original.height;
 
-        let c1 = 0.01 * 0.01;
-        let c2 = 0.03 * 0.03;
+        let Constants { c1, c2 } = c;
 
         debug_assert_eq!(original.mu.len(), modified.mu.len());
         debug_assert_eq!(original.img_sq_blur.len(), modified.img_sq_blur.len());
@@ -440,6 +1766,101 @@ fn to_dssim(ssim: f64) -> f64 {
     1.0 / ssim.max(f64::EPSILON) - 1.0
 }
 
+/// Alpha preprocessed for comparison, downsampled the same way as the image
+fn alpha_scales(alpha: ImgVec<f32>, image: &DssimImage<f32>) -> Result<Vec<DssimChan<f32>>, Error> {
+    let mut scales = Vec::with_capacity(image.scale.len());
+    let mut next = Some(alpha);
+    for (n, s) in image.scale.iter().enumerate() {
+        let alpha = next.take()
+            .filter(|a| a.width() == s.chan[0].width && a.height() == s.chan[0].height)
+            .ok_or(Error::ScaleCountMismatch { original: image.scale.len(), modified: scales.len() })?;
+        next = alpha.downsample_with(image.downsample_filter);
+        let pixels = alpha.width() * alpha.height();
+        let mut ch = DssimChan::new(alpha, false);
+        let mut tmp = Vec::with_capacity(pixels);
+        ch.preprocess(&mut tmp.spare_capacity_mut()[..pixels], image.scale_kernel(n), 0);
+        scales.push(ch);
+    }
+    Ok(scales)
+}
+
+pub(crate) fn validate_channel_weights(weights: [f64; 3]) -> Result<[f64; 3], Error> {
+    if !weights.iter().all(|&w| w.is_finite() && w >= 0.) || weights.iter().sum::<f64>() <= 0. {
+        return Err(Error::InvalidParameter("channel weights must be finite, non-negative, and not all zero"));
+    }
+    Ok(weights)
+}
+
+pub(crate) fn validate_chroma_blur(sigma: f64) -> Result<f64, Error> {
+    if !(0. ..=4.).contains(&sigma) {
+        return Err(Error::InvalidParameter("chroma blur sigma must be in 0..=4 range"));
+    }
+    Ok(sigma)
+}
+
+/// Premultiplied SDR pixels with white at the SDR reference white
+fn sdr_to_hdr(pixels: &mut [RGBAPLU]) {
+    for px in pixels {
+        px.r *= SDR_WHITE;
+        px.g *= SDR_WHITE;
+        px.b *= SDR_WHITE;
+    }
+}
+
+fn check_buffer_len(len: usize, width: usize, height: usize) -> Result<(), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::ZeroDimensions);
+    }
+    let expected = width.saturating_mul(height);
+    if len < expected {
+        return Err(Error::BufferTooSmall { expected, actual: len });
+    }
+    Ok(())
+}
+
+/// `compare_inner` relies on both images having the same structure
+fn check_compatible(original: &DssimImage<f32>, modified: &DssimImage<f32>) -> Result<(), Error> {
+    if original.algorithm != modified.algorithm {
+        return Err(Error::SettingsMismatch("images have been created for different algorithms"));
+    }
+    if original.color_model != modified.color_model {
+        return Err(Error::SettingsMismatch("images have been created with different color models"));
+    }
+    if original.chroma_blur != modified.chroma_blur {
+        return Err(Error::SettingsMismatch("images have been created with different chroma blur"));
+    }
+    if original.alpha_mode != modified.alpha_mode || original.alpha.is_some() != modified.alpha.is_some() {
+        return Err(Error::SettingsMismatch("images have been created with different alpha modes"));
+    }
+    if original.hdr != modified.hdr {
+        return Err(Error::SettingsMismatch("HDR images can't be compared with SDR images"));
+    }
+    if original.blur_kernel != modified.blur_kernel {
+        return Err(Error::SettingsMismatch("images have been created with different blur kernels"));
+    }
+    if original.downsample_filter != modified.downsample_filter {
+        return Err(Error::SettingsMismatch("images have been created with different downsample filters"));
+    }
+    if original.small_images != modified.small_images {
+        return Err(Error::SettingsMismatch("images have been created with different small-image modes"));
+    }
+    if original.scale.len() != modified.scale.len() {
+        return Err(Error::ScaleCountMismatch { original: original.scale.len(), modified: modified.scale.len() });
+    }
+    for (o, m) in original.scale.iter().zip(&modified.scale) {
+        if o.chan[0].width != m.chan[0].width || o.chan[0].height != m.chan[0].height {
+            return Err(Error::SizeMismatch {
+                original: (original.width(), original.height()),
+                modified: (modified.width(), modified.height()),
+            });
+        }
+        if o.chan.len() != m.chan.len() || !matches!(o.chan.len(), 1 | 3) {
+            return Err(Error::ChannelMismatch { original: o.chan.len(), modified: m.chan.len() });
+        }
+    }
+    Ok(())
+}
+
 #[test]
 fn png_compare() {
     use crate::linear::*;
@@ -454,11 +1875,11 @@ fn png_compare() {
     let img1 = d.create_image(&Img::new(buf1, file1.width, file1.height)).unwrap();
     let img2 = d.create_image(&Img::new(buf2, file2.width, file2.height)).unwrap();
 
-    let (res, _) = d.compare(&img1, img2);
+    let (res, _) = d.compare(&img1, img2).unwrap();
     assert!((0.001 - res).abs() < 0.0005, "res is {res}");
 
     let img1b = d.create_image(&Img::new(buf1, file1.width, file1.height)).unwrap();
-    let (res, _) = d.compare(&img1, img1b);
+    let (res, _) = d.compare(&img1, img1b).unwrap();
 
     assert!(0.000000000000001 > res);
     assert!(res < 0.000000000000001);
@@ -467,13 +1888,13 @@ fn png_compare() {
     let sub_img1 = d.create_image(&Img::new(buf1, file1.width, file1.height).sub_image(2,3,44,33)).unwrap();
     let sub_img2 = d.create_image(&Img::new(buf2, file2.width, file2.height).sub_image(17,9,44,33)).unwrap();
     // Test passing second image directly
-    let (res, _) = d.compare(&sub_img1, sub_img2);
+    let (res, _) = d.compare(&sub_img1, sub_img2).unwrap();
     assert!(res > 0.1);
 
     let sub_img1 = d.create_image(&Img::new(buf1, file1.width, file1.height).sub_image(22,8,61,40)).unwrap();
     let sub_img2 = d.create_image(&Img::new(buf2, file2.width, file2.height).sub_image(22,8,61,40)).unwrap();
     // Test passing second image as reference
-    let (res, _) = d.compare(&sub_img1, sub_img2);
+    let (res, _) = d.compare(&sub_img1, sub_img2).unwrap();
     assert!(res < 0.01);
 }
 
@@ -488,6 +1909,7 @@ fn png_compare() {
 ///   - upstream-vs-this-branch FP reordering drift (≤ 1.5×10⁻⁷),
 ///   - SIMD-path drift from PR2's `tolab` SIMD layer (≤ 5.6×10⁻⁷),
 ///   - SIMD ↔ scalar fallback divergence in PR2 (≤ 5.6×10⁻⁷),
+///
 /// with ≈10× margin. A real correctness bug — matrix typo, dropped scale
 /// weight, sigma sign-flip, edge-handling regression — moves SSIM by
 /// ≥10⁻³, so this bound catches everything that matters while admitting
@@ -512,7 +1934,8 @@ fn ssim_locked_values() {
         );
     }
 
-    let d = new();
+    // Upstream values are the scoring of 3.5, which differs for odd-sized scales
+    let d = Dssim::with_algorithm_version(AlgorithmVersion::V3_5);
     let file1 = lodepng::decode32_file("../tests/test1-sm.png").unwrap();
     let file2 = lodepng::decode32_file("../tests/test2-sm.png").unwrap();
     let buf1 = &file1.buffer.to_rgbaplu()[..];
@@ -525,30 +1948,55 @@ fn ssim_locked_values() {
     //    1.34×10⁻⁷ drift is FMA / 3-channel-combine reordering only.
     let a = d.create_image(&img1()).unwrap();
     let b = d.create_image(&img2()).unwrap();
-    let (got, _) = d.compare(&a, b);
+    let (got, _) = d.compare(&a, b).unwrap();
     approx_eq("full test1 vs test2", f64::from(got), 0.0009483923725199794);
 
     // 2. Identity: image vs itself must be exactly zero. Mathematical fact —
     //    any drift here means a real bug, not numerical noise.
     let a2 = d.create_image(&img1()).unwrap();
     let b2 = d.create_image(&img1()).unwrap();
-    let (got, _) = d.compare(&a2, b2);
+    let (got, _) = d.compare(&a2, b2).unwrap();
     assert_eq!(f64::from(got), 0.0, "identity must be exactly 0, got {got}");
 
     // 3. Sub-image regions of differing offsets — exercises the strided path
     //    (sub_image returns a non-tightly-packed view).
     let s1 = d.create_image(&img1().sub_image(2, 3, 44, 33)).unwrap();
     let s2 = d.create_image(&img2().sub_image(17, 9, 44, 33)).unwrap();
-    let (got, _) = d.compare(&s1, s2);
+    let (got, _) = d.compare(&s1, s2).unwrap();
     approx_eq("sub [2,3,44x33] vs [17,9,44x33]", f64::from(got), 0.10810340934514495);
 
     // 4. Sub-image regions with same offset — typical aligned-crop case.
     let s1 = d.create_image(&img1().sub_image(22, 8, 61, 40)).unwrap();
     let s2 = d.create_image(&img2().sub_image(22, 8, 61, 40)).unwrap();
-    let (got, _) = d.compare(&s1, s2);
+    let (got, _) = d.compare(&s1, s2).unwrap();
     approx_eq("sub [22,8,61x40] aligned", f64::from(got), 0.001675780079775091);
 }
 
+/// Same as `ssim_locked_values`, but for the scoring of 4.0, which keeps the last row and column of odd-sized scales
+#[test]
+fn ssim_locked_values_v4_0() {
+    use crate::linear::ToRGBAPLU;
+
+    let d = new();
+    assert_eq!(Some(AlgorithmVersion::V4_0), d.algorithm_version());
+    let file1 = lodepng::decode32_file("../tests/test1-sm.png").unwrap();
+    let file2 = lodepng::decode32_file("../tests/test2-sm.png").unwrap();
+    let buf1 = file1.buffer.to_rgbaplu();
+    let buf2 = file2.buffer.to_rgbaplu();
+    let img1 = Img::new(&buf1[..], file1.width, file1.height);
+    let img2 = Img::new(&buf2[..], file2.width, file2.height);
+
+    let v3_5 = Dssim::with_algorithm_version(AlgorithmVersion::V3_5);
+    for (left, top, width, height, expected) in [(2, 3, 45, 33, 0.0025542552266728524), (22, 8, 61, 41, 0.0018040043469975675)] {
+        let (crop1, crop2) = (img1.sub_image(left, top, width, height), img2.sub_image(left, top, width, height));
+        let (got, _) = d.compare(&d.create_image(&crop1).unwrap(), d.create_image(&crop2).unwrap()).unwrap();
+        assert!((f64::from(got) - expected).abs() <= 5e-6, "{left},{top} {width}x{height}: got {got}, expected {expected}");
+
+        let (old, _) = v3_5.compare(&v3_5.create_image(&crop1).unwrap(), v3_5.create_image(&crop2).unwrap()).unwrap();
+        assert!((f64::from(old) - expected).abs() > 5e-6, "{left},{top} {width}x{height} scored the same as in 3.5");
+    }
+}
+
 enum MaybeArc<'a, T> {
     Owned(Arc<T>),
     Borrowed(&'a T),
@@ -574,6 +2022,31 @@ impl<T> Deref for MaybeArc<'_, T> {
     }
 }
 
+/// The pair of similar images used by most tests
+#[cfg(test)]
+pub(crate) fn test_images() -> (ImgVec<RGBA<u8>>, ImgVec<RGBA<u8>>) {
+    let load = |path| {
+        let file = lodepng::decode32_file(path).unwrap();
+        ImgVec::new(file.buffer, file.width, file.height)
+    };
+    (load("../tests/test1-sm.png"), load("../tests/test2-sm.png"))
+}
+
+/// The image repeated vertically, with every other copy rotated by 180°
+#[cfg(test)]
+pub(crate) fn stack_rows(img: ImgRef<'_, RGBA<u8>>, copies: usize) -> ImgVec<RGBA<u8>> {
+    let buf: Vec<_> = img.pixels().collect();
+    let mut stacked = Vec::with_capacity(buf.len() * copies);
+    for n in 0..copies {
+        if n.is_multiple_of(2) {
+            stacked.extend_from_slice(&buf);
+        } else {
+            stacked.extend(buf.iter().rev());
+        }
+    }
+    ImgVec::new(stacked, img.width(), img.height() * copies)
+}
+
 #[test]
 fn poison() {
     let a = RGBAPLU::new(1.,1.,1.,1.);
@@ -592,6 +2065,844 @@ fn poison() {
     let d = new();
     let sub_img1 = d.create_image(&img.as_ref()).unwrap();
     let sub_img2 = d.create_image(&img.as_ref()).unwrap();
-    let (res, _) = d.compare(&sub_img1, sub_img2);
+    let (res, _) = d.compare(&sub_img1, sub_img2).unwrap();
     assert!(res < 0.000001);
 }
+
+#[test]
+fn errors() {
+    let d = new();
+    let px = vec![RGB::new(0u8, 0, 0); 10 * 10];
+    assert_eq!(Some(Error::BufferTooSmall { expected: 110, actual: 100 }), d.create_image_rgb(&px, 10, 11).err());
+    assert_eq!(Some(Error::ZeroDimensions), d.create_image_rgb(&px, 0, 10).err());
+    assert_eq!(Some(Error::ZeroDimensions), d.create_image(&ImgVec::<f32>::new(vec![], 10, 0)).err());
+
+    let img = d.create_image_rgb(&px, 10, 10).unwrap();
+    let small = d.create_image_rgb(&px, 10, 9).unwrap();
+    assert_eq!(Some(Error::SizeMismatch { original: (10, 10), modified: (10, 9) }), d.compare(&img, &small).err());
+
+    let gray = d.create_image(&ImgVec::new(vec![0.5f32; 10 * 10], 10, 10)).unwrap();
+    assert_eq!(Some(Error::ChannelMismatch { original: 3, modified: 1 }), d.compare(&img, &gray).err());
+
+    let mut d1 = new();
+    d1.set_scales(&[1.]);
+    let one_scale = d1.create_image_rgb(&px, 10, 10).unwrap();
+    assert_eq!(Some(Error::ScaleCountMismatch { original: 2, modified: 1 }), d.compare(&img, &one_scale).err());
+}
+
+#[test]
+fn report() {
+    let d = new();
+    let (rgba1, rgba2) = test_images();
+    let img1 = d.create_image_rgba(rgba1.buf(), rgba1.width(), rgba1.height()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), rgba2.width(), rgba2.height()).unwrap();
+
+    let (val, _) = d.compare(&img1, &img2).unwrap();
+    let report = d.compare_report(&img1, &img2).unwrap();
+    assert_eq!(val, report.dssim);
+    assert_eq!(DEFAULT_WEIGHTS.len(), report.scales.len());
+    assert_eq!(rgba1.width(), report.scales[0].width);
+    assert!(report.scales.windows(2).all(|w| w[0].width > w[1].width));
+    for (s, &w) in report.scales.iter().zip(&DEFAULT_WEIGHTS) {
+        assert_eq!(w, s.weight);
+        assert!(s.ssim > 0.9 && s.ssim < 1.0, "{}", s.ssim);
+        assert!(s.luma_ssim > 0.9 && s.luma_ssim < 1.0, "{}", s.luma_ssim);
+        let chroma = s.chroma_ssim.unwrap();
+        assert!(chroma > 0.9 && chroma <= 1.0, "{chroma}");
+    }
+
+    let same = d.compare_report(&img1, &img1).unwrap();
+    assert_eq!(0., same.dssim);
+    assert!(same.scales.iter().all(|s| s.luma_ssim == 1. && s.chroma_ssim == Some(1.)));
+}
+
+#[test]
+fn ssim_components() {
+    let mut d = new();
+    d.set_ssim_components(true);
+    let (rgba1, rgba2) = test_images();
+    let img1 = d.create_image_rgba(rgba1.buf(), rgba1.width(), rgba1.height()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), rgba2.width(), rgba2.height()).unwrap();
+
+    let report = d.compare_report(&img1, &img2).unwrap();
+    d.set_save_ssim_maps(1);
+    let (_, maps) = d.compare(&img1, &img2).unwrap();
+    let c = report.scales[0].components.as_ref().unwrap();
+    for (((l, c), s), ssim) in c.luminance_map.pixels().zip(c.contrast_map.pixels()).zip(c.structure_map.pixels()).zip(maps[0].map.pixels()) {
+        assert!((l * c * s - ssim).abs() < 1e-3, "{l}*{c}*{s} != {ssim}");
+    }
+    assert!(c.luminance > 0.99 && c.luminance <= 1.0);
+    assert!(c.contrast > 0.9 && c.contrast <= 1.0);
+    assert!(c.structure > 0.9 && c.structure <= 1.0);
+    assert!(report.scales.iter().all(|s| s.components.is_some()));
+}
+
+#[test]
+fn weight_mask() {
+    let d = new();
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let mut img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let (unmasked, _) = d.compare(&img1, &img2).unwrap();
+
+    img1.set_weight_mask(ImgVec::new(vec![1.; w * h], w, h).as_ref()).unwrap();
+    let (uniform, _) = d.compare(&img1, &img2).unwrap();
+    assert!((unmasked - uniform).abs() < 1e-9, "{unmasked} {uniform}");
+
+    // Top half differs, bottom is identical
+    let mut modified = rgba1.buf().to_vec();
+    modified[..w * h / 2].iter_mut().for_each(|px| px.r /= 2);
+    let img3 = d.create_image_rgba(&modified, w, h).unwrap();
+    let (half, _) = d.compare(&img1, &img3).unwrap();
+    let mut mask = vec![0.; w * h];
+    mask[w * h * 3 / 4..].fill(1.);
+    img1.set_weight_mask(ImgVec::new(mask, w, h).as_ref()).unwrap();
+    let (masked, _) = d.compare(&img1, &img3).unwrap();
+    assert!(half > 0.005 && masked < half / 10., "{half} {masked}");
+
+    img1.clear_weight_mask();
+    assert_eq!(unmasked, d.compare(&img1, &img2).unwrap().0);
+
+    assert!(img1.set_weight_mask(ImgVec::new(vec![1.; 4], 2, 2).as_ref()).is_err());
+    assert!(img1.set_weight_mask(ImgVec::new(vec![-1.; w * h], w, h).as_ref()).is_err());
+}
+
+#[test]
+fn pooling_setting() {
+    let mut d = new();
+    let (rgba1, rgba2) = test_images();
+    let img1 = d.create_image_rgba(rgba1.buf(), rgba1.width(), rgba1.height()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), rgba2.width(), rgba2.height()).unwrap();
+
+    let (mad, _) = d.compare(&img1, &img2).unwrap();
+    d.set_pooling(Pooling::Percentile(1.)).unwrap();
+    let (worst, _) = d.compare(&img1, &img2).unwrap();
+    assert!(worst > mad * 2., "{worst} {mad}");
+    assert_eq!(0., d.compare(&img1, &img1).unwrap().0);
+
+    assert!(d.set_pooling(Pooling::Minkowski(f64::NAN)).is_err());
+    d.set_pooling(Pooling::MeanAbsDeviation).unwrap();
+    assert_eq!(mad, d.compare(&img1, &img2).unwrap().0);
+}
+
+#[test]
+fn information_weighted_pooling() {
+    let (w, h) = (64, 64);
+    // Textured left half, and flat right half that is brighter in the modified image
+    let textured = |x: usize, y: usize| if (x / 2 + y / 2).is_multiple_of(2) { 40 } else { 200 };
+    let orig: Vec<_> = (0..w * h).map(|i| if i % w < w / 2 { textured(i % w, i / w) } else { 128 }).map(|v| RGB::new(v, v, v)).collect();
+    let brighter: Vec<_> = orig.iter().enumerate().map(|(i, &px)| if i % w < w / 2 { px } else { RGB::new(160, 160, 160) }).collect();
+
+    let mut d = new();
+    // The texture is too fine to survive in smaller scales
+    d.set_scales(&[0.5, 0.5]);
+    d.set_pooling(Pooling::Mean).unwrap();
+    let img1 = d.create_image_rgb(&orig, w, h).unwrap();
+    let img2 = d.create_image_rgb(&brighter, w, h).unwrap();
+    let (mean, _) = d.compare(&img1, &img2).unwrap();
+    d.set_pooling(Pooling::InformationWeighted).unwrap();
+    let (iw, _) = d.compare(&img1, &img2).unwrap();
+    assert!(iw < mean * 0.7, "{iw} {mean}");
+    assert_eq!(0., d.compare(&img1, &img1).unwrap().0);
+
+    // Flat images still differ
+    let flat1 = d.create_image_rgb(&vec![RGB::new(128, 128, 128); w * h], w, h).unwrap();
+    let flat2 = d.create_image_rgb(&vec![RGB::new(160, 160, 160); w * h], w, h).unwrap();
+    assert!(d.compare(&flat1, &flat2).unwrap().0 > 0.);
+
+    assert!(d.compare_tiled(Img::new(&orig[..], w, h), Img::new(&brighter[..], w, h), usize::MAX).is_err());
+}
+
+#[test]
+fn size_policy() {
+    use crate::linear::*;
+
+    let mut d = new();
+    let (rgba, _) = test_images();
+    let img = ImgVec::new(rgba.buf().to_rgbaplu(), rgba.width(), rgba.height());
+    let (w, h) = (img.width(), img.height());
+    let padded = ImgVec::new(img.rows().flat_map(|row| row.iter().copied().chain([RGBAPLU::new(1., 0., 0., 1.); 3])).collect(), w + 3, h);
+    assert_eq!(Some(Error::SizeMismatch { original: (w, h), modified: (w + 3, h) }), d.compare_images(img.as_ref(), padded.as_ref()).err());
+
+    d.set_size_policy(SizePolicy::CropToIntersection);
+    let report = d.compare_images(img.as_ref(), padded.as_ref()).unwrap();
+    assert_eq!(0., report.dssim);
+    assert_eq!(SizeAdjustment::Cropped { left: 0, top: 0, width: w, height: h }, report.size_adjustment);
+
+    let shifted = img.as_ref().sub_image(5, 2, w - 5, h - 2);
+    d.set_size_policy(SizePolicy::CropAt { x: 5, y: 2 });
+    let report = d.compare_images(img.as_ref(), shifted).unwrap();
+    assert_eq!(0., report.dssim);
+    assert_eq!(SizeAdjustment::Cropped { left: 5, top: 2, width: w - 5, height: h - 2 }, report.size_adjustment);
+    d.set_size_policy(SizePolicy::CropAt { x: -1000, y: 0 });
+    assert!(d.compare_images(img.as_ref(), shifted).is_err());
+
+    d.set_size_policy(SizePolicy::Resample);
+    let smaller = resample(img.as_ref(), w - 1, h - 1);
+    let report = d.compare_images(img.as_ref(), smaller.as_ref()).unwrap();
+    assert!(report.dssim > 0. && report.dssim < 0.02, "{}", report.dssim);
+    assert_eq!(SizeAdjustment::Resampled { width: w - 1, height: h - 1 }, report.size_adjustment);
+    assert_eq!(SizeAdjustment::None, d.compare_images(img.as_ref(), img.as_ref()).unwrap().size_adjustment);
+
+    // Same scores with the original created once
+    let original_image = d.create_image(&img).unwrap();
+    let reused = d.compare_images_with_original(img.as_ref(), &original_image, smaller.as_ref()).unwrap();
+    assert_eq!(report.dssim, reused.dssim);
+    assert!(reused.scales.iter().all(|s| s.chroma_ssim.is_none()));
+    d.set_size_policy(SizePolicy::CropAt { x: 5, y: 2 });
+    assert_eq!(0., d.compare_images_with_original(img.as_ref(), &original_image, shifted).unwrap().dssim);
+    assert!(d.compare_images_with_original(shifted, &original_image, shifted).is_err());
+}
+
+#[test]
+fn alignment() {
+    use crate::linear::*;
+
+    let mut d = new();
+    let (rgba, _) = test_images();
+    let img = ImgVec::new(rgba.buf().to_rgbaplu(), rgba.width(), rgba.height());
+    let (w, h) = (img.width(), img.height());
+    // Content moved right by 2.5px and down by 1px
+    let shifted = translate(img.as_ref(), -2.5, -1., w, h);
+
+    let unaligned = d.compare_images(img.as_ref(), shifted.as_ref()).unwrap();
+    assert!(unaligned.alignment.is_none());
+    d.set_alignment(8);
+    let aligned = d.compare_images(img.as_ref(), shifted.as_ref()).unwrap();
+    let a = aligned.alignment.unwrap();
+    assert!((a.dx - 2.5).abs() < 0.15 && (a.dy - 1.).abs() < 0.15, "{a:?}");
+    assert!(a.left <= 3 && a.top <= 2 && a.width >= w - 3 && a.height >= h - 2, "{a:?}");
+    assert!(aligned.dssim * 5. < unaligned.dssim, "{} {}", aligned.dssim, unaligned.dssim);
+
+    let same = d.compare_images(img.as_ref(), img.as_ref()).unwrap();
+    assert_eq!(0., same.dssim);
+    assert_eq!(Some(Alignment { dx: 0., dy: 0., left: 0, top: 0, width: w, height: h }), same.alignment);
+}
+
+#[test]
+fn tiled() {
+    let mut d = new();
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    // Tall enough to need several bands with all 5 scales
+    let (tall1, tall2) = (stack_rows(rgba1.as_ref(), 3), stack_rows(rgba2.as_ref(), 3));
+    let img1 = tall1.as_ref();
+    let img2 = tall2.as_ref();
+
+    let (whole, _) = d.compare(&d.create_image_rgba(tall1.buf(), w, h * 3).unwrap(), d.create_image_rgba(tall2.buf(), w, h * 3).unwrap()).unwrap();
+    let bands = d.compare_tiled(img1, img2, d.tiled_band_bytes(&d.tiled_scale_sizes(w, h * 3), 300)).unwrap();
+    assert!((whole - bands).abs() < 1e-5, "{whole} {bands}");
+    let one_band = d.compare_tiled(img1, img2, usize::MAX).unwrap();
+    assert!((whole - one_band).abs() < 1e-6, "{whole} {one_band}");
+    assert!(d.compare_tiled(img1, img2, 1000).is_err());
+
+    // Backgrounds of transparent pixels are aligned to the whole image, not bands
+    let translucent = |img: &ImgVec<RGBA<u8>>, step| ImgVec::new(img.pixels().enumerate().map(|(i, px)| RGBA { a: (i * step % 256) as u8, ..px }).collect::<Vec<_>>(), w, h * 3);
+    let (tall1, tall2) = (translucent(&tall1, 7), translucent(&tall2, 5));
+    for mode in [AlphaMode::Pattern, AlphaMode::Checkerboard { size: 5, light: [200, 200, 200], dark: [50, 50, 50] }] {
+        d.set_alpha_mode(mode).unwrap();
+        let (whole, _) = d.compare(&d.create_image_rgba(tall1.buf(), w, h * 3).unwrap(), d.create_image_rgba(tall2.buf(), w, h * 3).unwrap()).unwrap();
+        let bands = d.compare_tiled(tall1.as_ref(), tall2.as_ref(), d.tiled_band_bytes(&d.tiled_scale_sizes(w, h * 3), 300)).unwrap();
+        assert!((whole - bands).abs() < 1e-5, "{mode:?} {whole} {bands}");
+    }
+    d.set_alpha_mode(AlphaMode::Pattern).unwrap();
+
+    d.set_scales(&[0.3, 0.7]);
+    d.set_pooling(Pooling::Minkowski(2.)).unwrap();
+    let small1 = rgba1.as_ref();
+    let small2 = rgba2.as_ref();
+    let (whole, _) = d.compare(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    let bands = d.compare_tiled(small1, small2, d.tiled_band_bytes(&d.tiled_scale_sizes(w, h), 40)).unwrap();
+    assert!((whole - bands).abs() < 1e-5, "{whole} {bands}");
+}
+
+#[test]
+fn downsample_filter() {
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+
+    // Odd size, with a difference only in the last column
+    let (ow, oh) = ((w - 1) | 1, (h - 1) | 1);
+    let orig: Vec<_> = rgba1.buf().chunks(w).take(oh).flat_map(|row| &row[..ow]).copied().collect();
+    let mut edge = orig.clone();
+    for row in edge.chunks_mut(ow) {
+        row[ow - 1] = RGBA::new(0, 0, 0, 255);
+    }
+    let mut d = new();
+    let report = d.compare_report(&d.create_image_rgba(&orig, ow, oh).unwrap(), d.create_image_rgba(&edge, ow, oh).unwrap()).unwrap();
+    assert_eq!(ow.div_ceil(2), report.scales[1].width);
+    assert!(report.scales.iter().all(|s| s.ssim < 1.), "{:?}", report.scales.iter().map(|s| s.ssim).collect::<Vec<_>>());
+    let old = Dssim::with_algorithm_version(AlgorithmVersion::V3_5);
+    let report = old.compare_report(&old.create_image_rgba(&orig, ow, oh).unwrap(), old.create_image_rgba(&edge, ow, oh).unwrap()).unwrap();
+    assert_eq!(ow / 2, report.scales[1].width);
+    assert!(report.scales[1..].iter().all(|s| s.ssim == 1.));
+
+    let (tall1, tall2) = (stack_rows(rgba1.as_ref(), 3), stack_rows(rgba2.as_ref(), 3));
+    let (box_score, _) = d.compare(&d.create_image_rgba(tall1.buf(), w, h * 3).unwrap(), d.create_image_rgba(tall2.buf(), w, h * 3).unwrap()).unwrap();
+    for filter in [DownsampleFilter::Triangle, DownsampleFilter::Lanczos2] {
+        d.set_downsample_filter(filter);
+        assert_eq!(None, d.algorithm_version());
+        let img1 = d.create_image_rgba(tall1.buf(), w, h * 3).unwrap();
+        let mut img2 = d.create_image_rgba(tall2.buf(), w, h * 3).unwrap();
+        let (score, _) = d.compare(&img1, &img2).unwrap();
+        assert!(score > box_score * 0.5 && score < box_score * 2., "{filter:?} {score} {box_score}");
+        let bands = d.compare_tiled(tall1.as_ref(), tall2.as_ref(), d.tiled_band_bytes(&d.tiled_scale_sizes(w, h * 3), 500)).unwrap();
+        assert!((score - bands).abs() < 1e-5, "{filter:?} {score} {bands}");
+
+        img2.set_weight_mask(ImgVec::new((0..w * h * 3).map(|i| if i % w < 8 { 1. } else { 0. }).collect(), w, h * 3).as_ref()).unwrap();
+        assert!(img2.weight_mask.as_ref().unwrap().iter().all(|m| m.pixels().all(|w| w >= 0.)));
+        assert!(matches!(d.compare(&img1, old.create_image_rgba(tall2.buf(), w, h * 3).unwrap()), Err(Error::SettingsMismatch(_))));
+    }
+    d.set_downsample_filter(DownsampleFilter::Box);
+    assert_eq!(Some(AlgorithmVersion::V4_0), d.algorithm_version());
+}
+
+#[test]
+fn small_images() {
+    let (w, h) = (16, 16);
+    let icon: Vec<_> = (0..w * h).map(|i| {
+        let v: u8 = if (4..12).contains(&(i % w)) && (4..12).contains(&(i / w)) { 220 } else { 30 };
+        RGB::new(v, v / 2, 255 - v)
+    }).collect();
+    let redder: Vec<_> = icon.iter().map(|px| RGB::new(px.r.saturating_add(40), px.g, px.b)).collect();
+
+    let mut d = new();
+    let report = d.compare_report(&d.create_image_rgb(&icon, w, h).unwrap(), d.create_image_rgb(&redder, w, h).unwrap()).unwrap();
+    assert_eq!(3, report.scales.len());
+    assert_eq!(2, report.skipped_scales);
+    assert_eq!(DEFAULT_WEIGHTS[..3], report.scales.iter().map(|s| s.weight).collect::<Vec<_>>()[..]);
+
+    d.set_small_image_mode(true);
+    assert_eq!(None, d.algorithm_version());
+    let img1 = d.create_image_rgb(&icon, w, h).unwrap();
+    let img2 = d.create_image_rgb(&redder, w, h).unwrap();
+    let small = d.compare_report(&img1, &img2).unwrap();
+    // No 4×4 scale
+    assert_eq!(2, small.scales.len());
+    assert_eq!(3, small.skipped_scales);
+    assert!((small.scales.iter().map(|s| s.weight).sum::<f64>() - DEFAULT_WEIGHTS.iter().sum::<f64>()).abs() < 1e-9);
+    assert_eq!(report.scales[1].ssim, small.scales[1].ssim);
+    let tiled = d.compare_tiled(Img::new(&icon[..], w, h), Img::new(&redder[..], w, h), usize::MAX).unwrap();
+    assert!((small.dssim - tiled).abs() < 1e-5, "{} {tiled}", small.dssim);
+    assert!(small.dssim > 0. && small.dssim != report.dssim);
+    assert_eq!(0., d.compare(&img1, &img1).unwrap().0);
+    assert!(matches!(d.compare(&img1, new().create_image_rgb(&redder, w, h).unwrap()), Err(Error::SettingsMismatch(_))));
+
+    // Uniform changes of tiny images have no deviation from the average
+    for size in [1, 4] {
+        let black = vec![RGB::new(0u8, 0, 0); size * size];
+        let white = vec![RGB::new(255u8, 255, 255); size * size];
+        let default = new();
+        assert!(default.compare(&default.create_image_rgb(&black, size, size).unwrap(), default.create_image_rgb(&white, size, size).unwrap()).unwrap().0 < 0.001);
+
+        let black = d.create_image_rgb(&black, size, size).unwrap();
+        let white = d.create_image_rgb(&white, size, size).unwrap();
+        assert!(d.compare(&black, &white).unwrap().0 > 0.1);
+        assert!(!d.compare_with_threshold(&black, &white, 0.001).unwrap().passed);
+    }
+
+    // Images with all the scales aren't affected
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let large = d.compare_report(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    let d = new();
+    let (default, _) = d.compare(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    assert_eq!(default, large.dssim);
+    assert_eq!(0, large.skipped_scales);
+}
+
+#[test]
+fn threshold() {
+    let d = new();
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let (score, _) = d.compare(&img1, &img2).unwrap();
+
+    let above = d.compare_with_threshold(&img1, &img2, f64::from(score) * 1.01).unwrap();
+    let below = d.compare_with_threshold(&img1, &img2, f64::from(score) * 0.99).unwrap();
+    assert!(above.passed && !below.passed);
+    for r in [above, below] {
+        assert!(r.min_dssim <= score && score <= r.max_dssim, "{r:?} {score}");
+        if r.scales_compared == 5 {
+            assert_eq!(r.min_dssim, score);
+            assert_eq!(r.max_dssim, score);
+        }
+    }
+
+    // Clear failures don't need the large scales
+    let black = d.create_image_rgba(&vec![RGBA::new(0, 0, 0, 255); w * h], w, h).unwrap();
+    let r = d.compare_with_threshold(&img1, &black, 0.001).unwrap();
+    assert!(!r.passed && r.scales_compared < 3, "{r:?}");
+    assert!(r.min_dssim > 0.001);
+
+    let same = d.compare_with_threshold(&img1, &img1, 0.).unwrap();
+    assert!(same.passed);
+    assert_eq!(0., same.max_dssim);
+    assert!(d.compare_with_threshold(&img1, &img2, -1.).is_err());
+    assert!(d.compare_with_threshold(&img1, &img2, f64::NAN).is_err());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde() {
+    let mut d: Dssim = serde_json::from_str(r#"{"scale_weights": [0.5, 0.5], "pooling": {"Percentile": 5.0}, "save_maps_scales": 1}"#).unwrap();
+    assert_eq!(vec![0.5, 0.5], d.scale_weights);
+    assert_eq!(Pooling::Percentile(5.), d.pooling);
+    assert_eq!(SizePolicy::Exact, d.size_policy);
+    assert!(serde_json::from_str::<Dssim>(r#"{"pooling": {"Minkowski": 0.1}}"#).is_err());
+    d = serde_json::from_str(&serde_json::to_string(&d).unwrap()).unwrap();
+    assert_eq!(1, d.save_maps_scales);
+
+    let (rgba1, rgba2) = test_images();
+    let img1 = d.create_image_rgba(rgba1.buf(), rgba1.width(), rgba1.height()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), rgba2.width(), rgba2.height()).unwrap();
+    let report = d.compare_report(&img1, &img2).unwrap();
+    let json = serde_json::to_string(&report).unwrap();
+    let loaded: ComparisonReport = serde_json::from_str(&json).unwrap();
+    assert!((report.dssim - loaded.dssim).abs() < 1e-15);
+    let map = loaded.scales[0].ssim_map.as_ref().unwrap();
+    assert_eq!((rgba1.width(), rgba1.height()), (map.map.width(), map.map.height()));
+    assert!(loaded.scales[1].ssim_map.is_none());
+    assert_eq!(f64::from(report.dssim).to_string(), serde_json::to_string(&report.dssim).unwrap());
+}
+
+#[test]
+fn ssim_constants() {
+    let mut d = new();
+    let (rgba1, rgba2) = test_images();
+    let img1 = d.create_image_rgba(rgba1.buf(), rgba1.width(), rgba1.height()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), rgba2.width(), rgba2.height()).unwrap();
+
+    let (default, _) = d.compare(&img1, &img2).unwrap();
+    d.set_ssim_constants(0.01, 0.03, 1.).unwrap();
+    assert_eq!(default, d.compare(&img1, &img2).unwrap().0);
+
+    d.set_ssim_constants(0.001, 0.003, 1.).unwrap();
+    let (sensitive, _) = d.compare(&img1, &img2).unwrap();
+    d.set_ssim_constants(0.1, 0.3, 1.).unwrap();
+    let (lenient, _) = d.compare(&img1, &img2).unwrap();
+    assert!(sensitive > default && default > lenient, "{sensitive} {default} {lenient}");
+    // Only the product matters
+    d.set_ssim_constants(0.05, 0.15, 2.).unwrap();
+    assert!((lenient - d.compare(&img1, &img2).unwrap().0).abs() < 1e-6);
+
+    assert!(d.set_ssim_constants(0., 0.03, 1.).is_err());
+    assert!(d.set_ssim_constants(0.01, f64::INFINITY, 1.).is_err());
+    assert!(d.set_ssim_constants(0.01, 0.03, -1.).is_err());
+}
+
+#[test]
+fn blur_kernel() {
+    let mut d = new();
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let (default, _) = d.compare(&img1, d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+
+    d.set_blur_kernel(BlurKernel::Gaussian { sigma: 1.5 }).unwrap();
+    let wide1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let wide2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let (wide, _) = d.compare(&wide1, &wide2).unwrap();
+    assert!(wide > 0. && (wide - default).abs() > 1e-5, "{wide} {default}");
+    assert_eq!(0., d.compare(&wide1, &wide1).unwrap().0);
+    assert!(matches!(d.compare(&img1, &wide2), Err(Error::SettingsMismatch(_))));
+
+    let (tall1, tall2) = (stack_rows(rgba1.as_ref(), 2), stack_rows(rgba2.as_ref(), 2));
+    let (whole, _) = d.compare(&d.create_image_rgba(tall1.buf(), w, h * 2).unwrap(), d.create_image_rgba(tall2.buf(), w, h * 2).unwrap()).unwrap();
+    let bands = d.compare_tiled(tall1.as_ref(), tall2.as_ref(), d.tiled_band_bytes(&d.tiled_scale_sizes(w, h * 2), 360)).unwrap();
+    assert!((whole - bands).abs() < 1e-5, "{whole} {bands}");
+
+    assert!(d.set_blur_kernel(BlurKernel::Gaussian { sigma: 0. }).is_err());
+    assert!(d.set_blur_kernel(BlurKernel::Gaussian { sigma: f64::NAN }).is_err());
+}
+
+/// Straightforward port of the reference `ssim_index.m` and `msssim.m`, returning (SSIM, contrast-structure) of every scale
+///
+/// Its output hasn't been checked against MATLAB or scikit-image, so it only catches differences from the faster implementation.
+#[cfg(test)]
+fn reference_ssim(mut img1: ImgVec<f64>, mut img2: ImgVec<f64>, scales: usize) -> Vec<(f64, f64)> {
+    let window: Vec<f64> = (-5..=5).map(|i: i32| (-f64::from(i * i) / (2. * 1.5 * 1.5)).exp()).collect();
+    let norm = window.iter().sum::<f64>().powi(2);
+    let (c1, c2) = (0.01f64.powi(2), 0.03f64.powi(2));
+    let mut out = Vec::new();
+    for _ in 0..scales {
+        let (w, h) = (img1.width(), img1.height());
+        let (mut ssim_sum, mut cs_sum) = (0., 0.);
+        for y in 5..h - 5 {
+            for x in 5..w - 5 {
+                let (mut m1, mut m2, mut s11, mut s22, mut s12) = (0., 0., 0., 0., 0.);
+                for wy in 0..11 {
+                    for wx in 0..11 {
+                        let k = window[wy] * window[wx] / norm;
+                        let (a, b) = (img1[(x + wx - 5, y + wy - 5)], img2[(x + wx - 5, y + wy - 5)]);
+                        m1 += k * a; m2 += k * b;
+                        s11 += k * a * a; s22 += k * b * b; s12 += k * a * b;
+                    }
+                }
+                let (v1, v2, v12) = (s11 - m1 * m1, s22 - m2 * m2, s12 - m1 * m2);
+                let cs = (2. * v12 + c2) / (v1 + v2 + c2);
+                cs_sum += cs;
+                ssim_sum += (2. * m1 * m2 + c1) / (m1 * m1 + m2 * m2 + c1) * cs;
+            }
+        }
+        let n = ((w - 10) * (h - 10)) as f64;
+        out.push((ssim_sum / n, cs_sum / n));
+
+        // imfilter(img, ones(2)/4, 'symmetric') and img(1:2:end, 1:2:end)
+        let down = |img: &ImgVec<f64>| {
+            let px = |x: usize, y: usize| img[(x.min(w - 1), y.min(h - 1))];
+            let pixels = (0..h.div_ceil(2)).flat_map(|y| (0..w.div_ceil(2)).map(move |x| {
+                (px(2 * x, 2 * y) + px(2 * x + 1, 2 * y) + px(2 * x, 2 * y + 1) + px(2 * x + 1, 2 * y + 1)) / 4.
+            })).collect();
+            ImgVec::new(pixels, w.div_ceil(2), h.div_ceil(2))
+        };
+        (img1, img2) = (down(&img1), down(&img2));
+    }
+    out
+}
+
+#[test]
+fn classic_ssim() {
+    let file1 = lodepng::decode24_file("../tests/test1-sm.png").unwrap();
+    let file2 = lodepng::decode24_file("../tests/test2-sm.png").unwrap();
+    let (w, h) = (file1.width, file1.height);
+    let luma = |buf: &[RGB<u8>]| ImgVec::new(buf.iter().map(|p| (0.299 * f64::from(p.r) + 0.587 * f64::from(p.g) + 0.114 * f64::from(p.b)) / 255.).collect(), w, h);
+    let reference = reference_ssim(luma(&file1.buffer), luma(&file2.buffer), 5);
+
+    let mut d = new();
+    d.set_algorithm(Algorithm::Ssim);
+    let img1 = d.create_image_rgb(&file1.buffer, w, h).unwrap();
+    let report = d.compare_report(&img1, d.create_image_rgb(&file2.buffer, w, h).unwrap()).unwrap();
+    assert!((report.ssim - reference[0].0).abs() < 1e-5, "{} {}", report.ssim, reference[0].0);
+    assert_eq!(1, report.scales.len());
+    assert_eq!(1., d.compare_report(&img1, &img1).unwrap().ssim);
+
+    d.set_algorithm(Algorithm::MsSsim);
+    let img1 = d.create_image_rgb(&file1.buffer, w, h).unwrap();
+    let report = d.compare_report(&img1, d.create_image_rgb(&file2.buffer, w, h).unwrap()).unwrap();
+    let expected = reference.iter().zip(classic::MS_SSIM_WEIGHTS).enumerate()
+        .fold(1., |product, (n, (&(ssim, cs), weight))| product * if n == 4 { ssim } else { cs }.powf(weight));
+    assert!((report.ssim - expected).abs() < 1e-5, "{} {expected}", report.ssim);
+    assert_eq!(5, report.scales.len());
+    assert!((f64::from(report.dssim) - (1. / report.ssim - 1.)).abs() < 1e-9);
+
+    // Images for different algorithms can't be mixed
+    let dssim_img = new().create_image_rgb(&file1.buffer, w, h).unwrap();
+    assert!(matches!(d.compare(&img1, &dssim_img), Err(Error::SettingsMismatch(_))));
+    assert!(d.create_image_rgb(&file1.buffer[..100 * 100], 100, 100).is_err());
+    assert!(d.compare_tiled(Img::new(&file1.buffer[..], w, h), Img::new(&file2.buffer[..], w, h), 1 << 30).is_err());
+}
+
+#[test]
+fn pixel_metrics_report() {
+    let mut d = new();
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let without = d.compare_report(&img1, &img2).unwrap();
+    assert!(without.pixel_metrics.is_none());
+
+    d.set_pixel_metrics(true);
+    let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let report = d.compare_report(&img1, &img2).unwrap();
+    assert_eq!(without.dssim, report.dssim);
+    let m = report.pixel_metrics.unwrap();
+    assert!(m.srgb.psnr_rgb > 20. && m.srgb.psnr_rgb < 60., "{}", m.srgb.psnr_rgb);
+    assert!(m.ciede2000_p95 >= m.ciede2000_mean * 0.5 && m.ciede2000_mean > 0.);
+    assert_eq!((w, h), (m.ciede2000_map.width(), m.ciede2000_map.height()));
+
+    let mut data = Vec::new();
+    img1.write(&mut data).unwrap();
+    let loaded = DssimImage::read(&data[..]).unwrap();
+    let m2 = d.compare_report(&loaded, &img2).unwrap().pixel_metrics.unwrap();
+    assert_eq!(m.srgb, m2.srgb);
+
+    let same = d.compare_report(&img1, &img1).unwrap().pixel_metrics.unwrap();
+    assert_eq!([0.; 3], same.linear.mse);
+}
+
+#[test]
+fn algorithm_version() {
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let mut d = Dssim::with_algorithm_version(AlgorithmVersion::V3_5);
+    let report = d.compare_report(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    assert_eq!(Some(AlgorithmVersion::V3_5), report.algorithm_version);
+    assert!((f64::from(report.dssim) - 0.0009483923725199794).abs() < 5e-6);
+    assert_eq!(Some(AlgorithmVersion::LATEST), new().algorithm_version());
+
+    // Settings that don't affect the score
+    d.set_save_ssim_maps(2);
+    d.set_size_policy(SizePolicy::Resample);
+    assert_eq!(Some(AlgorithmVersion::V3_5), d.algorithm_version());
+
+    d.set_pooling(Pooling::Mean).unwrap();
+    assert_eq!(None, d.algorithm_version());
+    let report = d.compare_report(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    assert_eq!(None, report.algorithm_version);
+
+    // Images record the settings they've been created with
+    let mut custom = new();
+    custom.set_downsample_filter(DownsampleFilter::Triangle);
+    let (img1, img2) = (custom.create_image_rgba(rgba1.buf(), w, h).unwrap(), custom.create_image_rgba(rgba2.buf(), w, h).unwrap());
+    custom.set_downsample_filter(DownsampleFilter::Box);
+    assert_eq!(Some(AlgorithmVersion::LATEST), custom.algorithm_version());
+    assert_eq!(None, custom.compare_report(&img1, &img2).unwrap().algorithm_version);
+    let d = new();
+    let (img1, img2) = (d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap());
+    assert_eq!(Some(AlgorithmVersion::LATEST), d.compare_report(&img1, &img2).unwrap().algorithm_version);
+    let mut mean = new();
+    mean.set_pooling(Pooling::Mean).unwrap();
+    assert_eq!(Some(AlgorithmVersion::LATEST), mean.create_image_rgba(rgba1.buf(), w, h).unwrap().algorithm_version);
+}
+
+#[test]
+fn color_space() {
+    let (rgba, _) = test_images();
+    let (w, h) = (rgba.width(), rgba.height());
+    let srgb = ImgVec::new(rgba.buf().to_rgblu(), w, h);
+
+    // The same colors expressed with P3 primaries: p3 = inverse(P3) · sRGB · rgb
+    let [a, b] = [ColorSpace::DisplayP3.to_xyz_matrix(), ColorSpace::Srgb.to_xyz_matrix()].map(|m| m.map(|r| r.map(f64::from)));
+    let det = a[0][0] * (a[1][1] * a[2][2] - a[1][2] * a[2][1]) - a[0][1] * (a[1][0] * a[2][2] - a[1][2] * a[2][0]) + a[0][2] * (a[1][0] * a[2][1] - a[1][1] * a[2][0]);
+    let inv = |r: usize, c: usize| {
+        let (r0, r1, c0, c1) = ((c + 1) % 3, (c + 2) % 3, (r + 1) % 3, (r + 2) % 3);
+        (a[r0][c0] * a[r1][c1] - a[r0][c1] * a[r1][c0]) / det
+    };
+    let m: Vec<[f64; 3]> = (0..3).map(|r| [0, 1, 2].map(|c| (0..3).map(|k| inv(r, k) * b[k][c]).sum())).collect();
+    let p3 = ImgVec::new(srgb.pixels().map(|px| {
+        let [r, g, b] = [0, 1, 2].map(|i| (m[i][0] * f64::from(px.r) + m[i][1] * f64::from(px.g) + m[i][2] * f64::from(px.b)) as f32);
+        RGBLU { r, g, b }
+    }).collect(), w, h);
+
+    let mut d = new();
+    let original = d.create_image(&srgb).unwrap();
+    let (as_srgb, _) = d.compare(&original, d.create_image(&p3).unwrap()).unwrap();
+    assert!(as_srgb > 0.0001, "{as_srgb}");
+    d.set_color_space(ColorSpace::DisplayP3).unwrap();
+    let (as_p3, _) = d.compare(&original, d.create_image(&p3).unwrap()).unwrap();
+    assert!(as_p3 < 0.00001, "{as_p3}");
+
+    // Out of the sRGB gamut
+    let green = ImgVec::new(vec![RGBLU { r: 0., g: 1., b: 0. }; 64 * 64], 64, 64);
+    let p3_green = d.create_image(&green).unwrap();
+    d.set_color_space(ColorSpace::Srgb).unwrap();
+    let (diff, _) = d.compare(&d.create_image(&green).unwrap(), p3_green).unwrap();
+    assert!(diff > 0.001, "{diff}");
+
+    assert!(d.set_color_space(ColorSpace::Custom([[0.; 3]; 3])).is_err());
+}
+
+#[test]
+fn hdr() {
+    use crate::linear::pq_encode;
+
+    let (rgba, _) = test_images();
+    let (w, h) = (rgba.width(), rgba.height());
+    let rgb: Vec<RGB<u8>> = rgba.buf().iter().map(|px| px.rgb()).collect();
+    // The same image in 12-bit PQ
+    let pq: Vec<RGB<u16>> = rgb.to_rgblu().iter().map(|px| px.map(|c| (pq_encode(c * SDR_WHITE) * 4095.).round() as u16)).collect();
+
+    let mut d = new();
+    d.set_hdr(true);
+    let sdr = d.create_image_rgb(&rgb, w, h).unwrap();
+    let hdr = d.create_image_hdr(&pq, w, h, 12, HdrTransfer::Pq).unwrap();
+    let (diff, _) = d.compare(&sdr, &hdr).unwrap();
+    assert!(diff < 0.0001, "{diff}");
+
+    // Highlights brighter than SDR white still matter
+    let bright = |gain: f32| ImgVec::new(rgb.to_rgblu().iter().map(|px| px.map(|c| c * gain * SDR_WHITE)).collect(), w, h);
+    let (brighter, _) = d.compare(&d.create_image(&bright(4.)).unwrap(), d.create_image(&bright(5.)).unwrap()).unwrap();
+    let (darker, _) = d.compare(&d.create_image(&bright(0.8)).unwrap(), d.create_image(&bright(1.)).unwrap()).unwrap();
+    assert!(brighter > darker * 0.5 && brighter < darker * 2., "{brighter} {darker}");
+
+    let mut saved = Vec::new();
+    hdr.write(&mut saved).unwrap();
+    let loaded = DssimImage::read(&saved[..]).unwrap();
+    assert_eq!(0., f64::from(d.compare(&hdr, &loaded).unwrap().0));
+
+    // HLG reference white is about the same as SDR white
+    let hlg: Vec<RGB<u16>> = vec![RGB::new(768, 768, 768); 64 * 64];
+    d.set_color_space(ColorSpace::Rec2020).unwrap();
+    let hlg = d.create_image_hdr(&hlg, 64, 64, 10, HdrTransfer::Hlg).unwrap();
+    let white = d.create_image_rgb(&vec![RGB::new(255, 255, 255); 64 * 64], 64, 64).unwrap();
+    assert!(d.compare(&white, &hlg).unwrap().0 < 0.0001);
+
+    assert!(d.create_image_hdr(&pq, w, h, 17, HdrTransfer::Pq).is_err());
+    assert!(d.algorithm_version().is_none());
+    d.set_hdr(false);
+    assert!(matches!(d.compare(&d.create_image_rgb(&rgb, w, h).unwrap(), &hdr), Err(Error::SettingsMismatch(_))));
+    d.set_algorithm(Algorithm::Ssim);
+    assert!(d.create_image_hdr(&pq, w, h, 12, HdrTransfer::Pq).is_err());
+}
+
+#[test]
+fn color_model() {
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+
+    let mut d = new();
+    let lab = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let (lab_diff, _) = d.compare(&lab, d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    for model in [ColorModel::Oklab, ColorModel::YCbCr] {
+        d.set_color_model(model.clone());
+        let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+        let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+        assert_eq!(3, img1.scale[0].chan.len());
+        assert!(!img1.scale[0].chan[0].is_chroma && img1.scale[0].chan[1].is_chroma && img1.scale[0].chan[2].is_chroma);
+        assert_eq!(0., f64::from(d.compare(&img1, &img1).unwrap().0));
+        let (diff, _) = d.compare(&img1, &img2).unwrap();
+        assert!(diff > lab_diff * 0.2 && diff < lab_diff * 5., "{model:?} {diff} {lab_diff}");
+        assert!(matches!(d.compare(&lab, &img2), Err(Error::SettingsMismatch(_))));
+        assert!(d.algorithm_version().is_none());
+
+        let mut saved = Vec::new();
+        img2.write(&mut saved).unwrap();
+        assert_eq!(diff, d.compare(&img1, DssimImage::read(&saved[..]).unwrap()).unwrap().0);
+    }
+
+    // Own conversion to L*a*b* gives the same score as the built-in one
+    let custom = CustomColorModel::new(|px| {
+        let (l, a, b) = crate::tolab::ToLAB::to_lab(&px);
+        [l, a, b]
+    });
+    d.set_color_model(ColorModel::Custom(custom.clone()));
+    let img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    assert!(!img1.scale[0].chan[0].is_chroma && img1.scale[0].chan[1].is_chroma && img1.scale[0].chan[2].is_chroma);
+    let (diff, _) = d.compare(&img1, &img2).unwrap();
+    assert!((diff - lab_diff).abs() < 1e-6, "{diff} {lab_diff}");
+    assert!(d.algorithm_version().is_none());
+    assert!(matches!(d.compare(&lab, &img2), Err(Error::SettingsMismatch(_))));
+    assert!(img1.write(&mut Vec::new()).is_err());
+    assert_eq!(ColorModel::Custom(custom.clone()), ColorModel::Custom(custom));
+    d.set_color_model(ColorModel::Custom(CustomColorModel::new(|px| [px.g, px.r, px.b])));
+    assert!(matches!(d.compare(&img1, d.create_image_rgba(rgba2.buf(), w, h).unwrap()), Err(Error::SettingsMismatch(_))));
+
+    let gray = ImgVec::new(vec![0.5f32; 64 * 64], 64, 64);
+    assert_eq!(1, d.create_image(&gray).unwrap().scale[0].chan.len());
+    d.set_hdr(true);
+    assert!(d.create_image(&gray).is_err());
+}
+
+#[test]
+fn channel_weights() {
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+
+    let mut d = new();
+    let color1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let color2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let report = d.compare_report(&color1, &color2).unwrap();
+
+    // Luma-only images skip chroma, and score the same as luma of color images
+    d.set_channel_weights([1., 0., 0.]).unwrap();
+    assert!(d.algorithm_version().is_none());
+    let luma1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let luma2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    assert_eq!(1, luma1.scale[0].chan.len());
+    let luma_only = d.compare_report(&luma1, &luma2).unwrap();
+    for (l, c) in luma_only.scales.iter().zip(&report.scales) {
+        assert!((l.ssim - c.luma_ssim).abs() < 1e-6, "{} {}", l.ssim, c.luma_ssim);
+    }
+    let (weighted, _) = d.compare(&color1, &color2).unwrap();
+    assert!((weighted - luma_only.dssim).abs() < 1e-6, "{weighted} {}", luma_only.dssim);
+    assert!(matches!(d.compare(&color1, &luma2), Err(Error::ChannelMismatch { .. })));
+
+    d.set_channel_weights([1., 2., 2.]).unwrap();
+    let (chroma_heavy, _) = d.compare(&color1, &color2).unwrap();
+    assert!(chroma_heavy != report.dssim);
+    assert!(d.set_channel_weights([0., 0., 0.]).is_err());
+    assert!(d.set_channel_weights([1., -1., 0.]).is_err());
+    assert!(d.set_channel_weights([1., f64::NAN, 0.]).is_err());
+
+    // Chroma blurred less shows more of the differences
+    let mut d = new();
+    assert_eq!(1, d.chroma_blur_passes());
+    d.set_chroma_blur(0.).unwrap();
+    let sharp1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    let sharp_report = d.compare_report(&sharp1, d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    assert!(sharp_report.scales[0].chroma_ssim.unwrap() < report.scales[0].chroma_ssim.unwrap());
+    assert!(matches!(d.compare(&sharp1, &color2), Err(Error::SettingsMismatch(_))));
+    assert!(d.set_chroma_blur(5.).is_err());
+    assert!(d.set_chroma_blur(-1.).is_err());
+    assert!(d.set_chroma_blur(f64::NAN).is_err());
+
+    // Tiles have enough margin for stronger blur
+    d.set_chroma_blur(2.2).unwrap();
+    assert_eq!(4, d.chroma_blur_passes());
+    d.set_scales(&[0.3, 0.7]);
+    let (whole, _) = d.compare(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap();
+    let bands = d.compare_tiled(rgba1.as_ref(), rgba2.as_ref(), d.tiled_band_bytes(&d.tiled_scale_sizes(w, h), 80)).unwrap();
+    assert!((whole - bands).abs() < 1e-5, "{whole} {bands}");
+}
+
+#[test]
+fn alpha_mode() {
+    let (w, h) = (64, 64);
+    let transparent = vec![RGBA::new(0u8, 0, 0, 0); w * h];
+    let white = vec![RGBA::new(255u8, 255, 255, 255); w * h];
+    let black = vec![RGBA::new(0u8, 0, 0, 255); w * h];
+    let mut d = new();
+    let diff = |d: &Dssim, a: &[RGBA<u8>], b: &[RGBA<u8>]| d.compare_report(&d.create_image_rgba(a, w, h).unwrap(), d.create_image_rgba(b, w, h).unwrap()).unwrap();
+
+    assert!(diff(&d, &transparent, &white).dssim > 0.01);
+    d.set_alpha_mode(AlphaMode::Background([255, 255, 255])).unwrap();
+    let report = diff(&d, &transparent, &white);
+    assert!(report.dssim < 1e-6, "{}", report.dssim);
+    assert_eq!(AlphaMode::Background([255, 255, 255]), report.alpha_mode);
+    assert!(d.algorithm_version().is_none());
+
+    let (light, dark) = ([200, 200, 200], [100, 100, 100]);
+    let checkerboard: Vec<_> = (0..w * h).map(|i| {
+        let c = if ((i % w) / 8 + (i / w) / 8) % 2 == 0 { light } else { dark };
+        RGBA::new(c[0], c[1], c[2], 255)
+    }).collect();
+    d.set_alpha_mode(AlphaMode::Checkerboard { size: 8, light, dark }).unwrap();
+    assert!(diff(&d, &transparent, &checkerboard).dssim < 1e-6);
+    assert!(diff(&d, &transparent, &white).dssim > 0.01);
+    assert!(d.compare_tiled(Img::new(&transparent[..], w, h), Img::new(&checkerboard[..], w, h), usize::MAX).unwrap() < 1e-6);
+
+    // Same color over black, but different alpha
+    let hole: Vec<_> = (0..w * h).map(|i| if (20..40).contains(&(i % w)) && (20..40).contains(&(i / w)) { transparent[i] } else { black[i] }).collect();
+    d.set_alpha_mode(AlphaMode::Separate { weight: 1. }).unwrap();
+    assert!(d.compare_tiled(Img::new(&transparent[..], w, h), Img::new(&transparent[..], w, h), usize::MAX).is_err());
+    let report = diff(&d, &black, &hole);
+    assert!(report.dssim > 0.01);
+    assert!(report.scales[0].luma_ssim > 0.9999 && report.scales[0].alpha_ssim.unwrap() < 0.99, "{:?}", report.scales[0].alpha_ssim);
+    let original = d.create_image_rgba(&black, w, h).unwrap();
+    let mut saved = Vec::new();
+    original.write(&mut saved).unwrap();
+    let loaded = DssimImage::read(&saved[..]).unwrap();
+    assert_eq!(report.dssim, d.compare(&loaded, d.create_image_rgba(&hole, w, h).unwrap()).unwrap().0);
+    // Opaque images have opaque alpha
+    let rgb = d.create_image_rgb(&vec![RGB::new(0, 0, 0); w * h], w, h).unwrap();
+    assert_eq!(0., f64::from(d.compare(&original, &rgb).unwrap().0));
+    assert!(matches!(d.compare(&original, new().create_image_rgba(&black, w, h).unwrap()), Err(Error::SettingsMismatch(_))));
+
+    d.set_alpha_mode(AlphaMode::Separate { weight: 0. }).unwrap();
+    assert!(diff(&d, &black, &hole).dssim < 1e-6);
+    assert!(d.set_alpha_mode(AlphaMode::Separate { weight: -1. }).is_err());
+    assert!(d.set_alpha_mode(AlphaMode::Checkerboard { size: 0, light, dark }).is_err());
+}
+
+#[test]
+fn viewing_conditions() {
+    let (rgba1, rgba2) = test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let score = |ppd: f64| {
+        let mut d = new();
+        d.set_viewing_conditions(ViewingConditions::from_pixels_per_degree(ppd).unwrap());
+        d.compare_report(&d.create_image_rgba(rgba1.buf(), w, h).unwrap(), d.create_image_rgba(rgba2.buf(), w, h).unwrap()).unwrap()
+    };
+    let (desktop, phone) = (score(40.), score(95.));
+    assert_eq!(5, desktop.scales.len());
+    assert!(desktop.algorithm_version.is_none());
+    assert!(desktop.dssim != phone.dssim);
+    assert!(phone.scales[0].weight < desktop.scales[0].weight);
+}
@@ -0,0 +1,52 @@
+use std::{fmt, io};
+
+/// Reason why an image couldn't be created or compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The pixel buffer has fewer pixels than `width * height`
+    BufferTooSmall { expected: usize, actual: usize },
+    /// Width or height is zero
+    ZeroDimensions,
+    /// Images being compared have different width or height
+    SizeMismatch { original: (usize, usize), modified: (usize, usize) },
+    /// One image is color and the other is grayscale, or the color conversion returned an unsupported number of planes
+    ChannelMismatch { original: usize, modified: usize },
+    /// Images have been created with different number of scales (e.g. using differently configured `Dssim`)
+    ScaleCountMismatch { original: usize, modified: usize },
+    /// A setting or a weight is out of range
+    InvalidParameter(&'static str),
+    /// Reading or writing of a serialized image failed
+    Io(io::ErrorKind),
+    /// Serialized image is not in the expected format, or is corrupted
+    InvalidData(&'static str),
+    /// Serialized image has been made by a version of the library that preprocesses images differently
+    IncompatibleVersion { expected: u32, found: u32 },
+    /// Images have been created with different settings that change how they're preprocessed
+    SettingsMismatch(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall { expected, actual } => write!(f, "pixel buffer is too small: needs {expected} pixels, but has {actual}"),
+            Self::ZeroDimensions => f.write_str("image width and height must be greater than zero"),
+            Self::SizeMismatch { original, modified } => write!(f, "images have different sizes: {}x{} vs {}x{}", original.0, original.1, modified.0, modified.1),
+            Self::ChannelMismatch { original, modified } => write!(f, "images have incompatible channel layouts: {original} vs {modified} planes"),
+            Self::ScaleCountMismatch { original, modified } => write!(f, "images have different number of scales: {original} vs {modified}"),
+            Self::InvalidParameter(msg) => f.write_str(msg),
+            Self::Io(kind) => write!(f, "I/O error: {kind}"),
+            Self::InvalidData(msg) => write!(f, "invalid serialized image: {msg}"),
+            Self::SettingsMismatch(msg) => f.write_str(msg),
+            Self::IncompatibleVersion { expected, found } => write!(f, "serialized image has format version {found}, but only {expected} is supported"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.kind())
+    }
+}
@@ -118,11 +118,12 @@ impl std::ops::Div<Self> for LAB {
     }
 }
 
-/// Component-wise averaging of pixel values used by `Downsample` to support arbitrary pixel types
+/// Component-wise averaging of pixel values used by [`Downsample`] to support arbitrary pixel types
 ///
-/// Used to naively resample 4 high-res pixels into one low-res pixel
-#[doc(hidden)]
+/// Used to naively resample 4 high-res pixels into one low-res pixel. Implement it, and [`MixAdd`],
+/// for your own pixel type to downsample images of it with [`DownsampleFilter::Box`].
 pub trait Average4 {
+    /// Average of the four pixels
     fn average4(a: Self, b: Self, c: Self, d: Self) -> Self;
 }
 
@@ -153,6 +154,45 @@ impl Average4 for RGBLU {
     }
 }
 
+/// Weighed sum of pixel values used by [`Downsample`] to support arbitrary pixel types
+///
+/// Used by the [`DownsampleFilter`]s that have more than 2×2 taps. `Default` must be the zero pixel, which sums start from.
+/// Pixels should be in linear light, otherwise the filters change their brightness.
+pub trait MixAdd: Copy + Default {
+    /// `self + other * weight`
+    fn mix_add(self, other: Self, weight: f32) -> Self;
+}
+
+impl MixAdd for f32 {
+    #[inline(always)]
+    fn mix_add(self, other: Self, weight: f32) -> Self {
+        other.mul_add(weight, self)
+    }
+}
+
+impl MixAdd for RGBAPLU {
+    #[inline(always)]
+    fn mix_add(self, other: Self, weight: f32) -> Self {
+        RGBAPLU {
+            r: self.r.mix_add(other.r, weight),
+            g: self.g.mix_add(other.g, weight),
+            b: self.b.mix_add(other.b, weight),
+            a: self.a.mix_add(other.a, weight),
+        }
+    }
+}
+
+impl MixAdd for RGBLU {
+    #[inline(always)]
+    fn mix_add(self, other: Self, weight: f32) -> Self {
+        RGBLU {
+            r: self.r.mix_add(other.r, weight),
+            g: self.g.mix_add(other.g, weight),
+            b: self.b.mix_add(other.b, weight),
+        }
+    }
+}
+
 pub(crate) trait ToRGB {
     fn to_rgb(self, n: usize) -> RGBLU;
 }
@@ -179,30 +219,77 @@ impl ToRGB for RGBAPLU {
     }
 }
 
+/// Filter used for making smaller scales of images. See [`Dssim::set_downsample_filter()`][crate::Dssim::set_downsample_filter()]
+///
+/// Images with odd width or height are scaled to half the size rounded up, with the edge pixels repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DownsampleFilter {
+    /// Average of 2×2 pixels. This is the default, and the fastest.
+    #[default]
+    Box,
+    /// Triangle filter over 4×4 pixels. Slightly blurrier, with less aliasing.
+    Triangle,
+    /// Two-lobe Lanczos filter over 8×8 pixels. Sharp, with the least aliasing, but it can ring around sharp edges.
+    Lanczos2,
+    /// Average of 2×2 pixels, dropping the last row or column of odd-sized images. Used by [`AlgorithmVersion::V3_5`][crate::AlgorithmVersion::V3_5].
+    BoxTruncating,
+}
+
+impl DownsampleFilter {
+    /// Weights of source pixels for a destination pixel, centered between the two source pixels it covers
+    pub(crate) fn taps(self) -> &'static [f32] {
+        match self {
+            Self::Box | Self::BoxTruncating => &[0.5, 0.5],
+            Self::Triangle => &[0.125, 0.375, 0.375, 0.125],
+            Self::Lanczos2 => &[-0.008863332, -0.041940033, 0.116500095, 0.43430328, 0.43430328, 0.116500095, -0.041940033, -0.008863332],
+        }
+    }
+}
+
 /// You can customize how images are downsampled
 ///
 /// Multi-scale DSSIM needs to scale images down. This is it. It's supposed to return the same type of image, but half the size.
 ///
-/// There is a default implementation that just averages 4 neighboring pixels.
-#[doc(hidden)]
+/// There is an implementation for images of [`Average4`] and [`MixAdd`] pixels, which supports all the [`DownsampleFilter`]s.
+/// It stops at images smaller than 8×8 pixels.
 pub trait Downsample {
     type Output;
+
+    /// Half-size image, or `None` if the image is too small to be scaled down.
     fn downsample(&self) -> Option<Self::Output>;
+
+    /// Half-size image made with the given filter, or `None` if the image is too small to be scaled down.
+    ///
+    /// By default the filter is ignored, and it's the same as [`Downsample::downsample()`].
+    /// Implement it to support [`Dssim::set_downsample_filter()`][crate::Dssim::set_downsample_filter()].
+    fn downsample_with(&self, filter: DownsampleFilter) -> Option<Self::Output> {
+        let _ = filter;
+        self.downsample()
+    }
 }
 
-impl<T> Downsample for ImgVec<T> where T: Average4 + Copy + Sync + Send {
+impl<T> Downsample for ImgVec<T> where T: Average4 + MixAdd + Sync + Send {
     type Output = Self;
 
     fn downsample(&self) -> Option<Self::Output> {
         self.as_ref().downsample()
     }
+
+    fn downsample_with(&self, filter: DownsampleFilter) -> Option<Self::Output> {
+        self.as_ref().downsample_with(filter)
+    }
 }
 
-impl<T> Downsample for ImgRef<'_, T> where T: Average4 + Copy + Sync + Send {
+impl<T> Downsample for ImgRef<'_, T> where T: Average4 + MixAdd + Sync + Send {
     type Output = ImgVec<T>;
 
     fn downsample(&self) -> Option<Self::Output> {
-        let stride = self.stride();
+        self.downsample_with(DownsampleFilter::default())
+    }
+
+    fn downsample_with(&self, filter: DownsampleFilter) -> Option<Self::Output> {
         let width = self.width();
         let height = self.height();
 
@@ -210,18 +297,25 @@ impl<T> Downsample for ImgRef<'_, T> where T: Average4 + Copy + Sync + Send {
             return None;
         }
 
-        let half_height = height / 2;
-        let half_width = width / 2;
+        if !matches!(filter, DownsampleFilter::Box | DownsampleFilter::BoxTruncating) {
+            return Some(crate::resample::halve(*self, filter.taps()));
+        }
+
+        let (half_width, half_height) = if filter == DownsampleFilter::BoxTruncating {
+            (width / 2, height / 2)
+        } else {
+            (width.div_ceil(2), height.div_ceil(2))
+        };
 
         let mut scaled = Vec::with_capacity(half_width * half_height);
-        scaled.extend(self.buf().chunks(stride * 2).take(half_height).flat_map(|pair| {
-            let (top, bot) = pair.split_at(stride);
-            let top = &top[0..half_width * 2];
-            let bot = &bot[0..half_width * 2];
-
-            top.chunks_exact(2)
-                .zip(bot.chunks_exact(2))
-                .map(|(a, b)| Average4::average4(a[0], a[1], b[0], b[1]))
+        scaled.extend((0..half_height).flat_map(|y| {
+            // odd edges are averaged with themselves
+            let top = &self[2 * y];
+            let bot = &self[(2 * y + 1).min(height - 1)];
+            (0..half_width).map(move |x| {
+                let (x0, x1) = (2 * x, (2 * x + 1).min(width - 1));
+                Average4::average4(top[x0], top[x1], bot[x0], bot[x1])
+            })
         }));
 
         assert_eq!(half_width * half_height, scaled.len());
@@ -229,77 +323,36 @@ impl<T> Downsample for ImgRef<'_, T> where T: Average4 + Copy + Sync + Send {
     }
 }
 
-#[allow(dead_code)]
-pub(crate) fn worst(input: ImgRef<'_, f32>) -> ImgVec<f32> {
-    let stride = input.stride();
-    let half_height = input.height() / 2;
-    let half_width = input.width() / 2;
-
-    if half_height < 4 || half_width < 4 {
-        return input.new_buf(input.buf().to_vec());
-    }
-
-    let mut scaled = Vec::with_capacity(half_width * half_height);
-    scaled.extend(input.buf().chunks(stride * 2).take(half_height).flat_map(|pair| {
-        let (top, bot) = pair.split_at(stride);
-        let top = &top[0..half_width * 2];
-        let bot = &bot[0..half_width * 2];
-
-        top.chunks_exact(2).zip(bot.chunks_exact(2)).map(|(a,b)| {
-            a[0].min(a[1]).min(b[0].min(b[1]))
-        })
-    }));
-
-    assert_eq!(half_width * half_height, scaled.len());
-    Img::new(scaled, half_width, half_height)
-}
-
-#[allow(dead_code)]
-pub(crate) fn avgworst(input: ImgRef<'_, f32>) -> ImgVec<f32> {
-    let stride = input.stride();
-    let half_height = input.height() / 2;
-    let half_width = input.width() / 2;
-
-    if half_height < 4 || half_width < 4 {
-        return input.new_buf(input.buf().to_vec());
+#[test]
+fn downsample_odd() {
+    let img = ImgVec::new((0..9 * 8).map(|i| i as f32).collect::<Vec<_>>(), 9, 8);
+    let half = img.downsample().unwrap();
+    assert_eq!((5, 4), (half.width(), half.height()));
+    assert_eq!(5., half.buf()[0]);
+    // the last column is averaged with itself
+    assert_eq!(12.5, half.buf()[4]);
+
+    let truncated = img.downsample_with(DownsampleFilter::BoxTruncating).unwrap();
+    assert_eq!((4, 4), (truncated.width(), truncated.height()));
+    assert_eq!(half.buf()[..4], truncated.buf()[..4]);
+
+    for filter in [DownsampleFilter::Triangle, DownsampleFilter::Lanczos2] {
+        let flat = ImgVec::new(vec![0.25f32; 11 * 9], 11, 9).downsample_with(filter).unwrap();
+        assert_eq!((6, 5), (flat.width(), flat.height()));
+        assert!(flat.pixels().all(|p| (p - 0.25).abs() < 1e-6), "{filter:?}");
     }
-
-    let mut scaled = Vec::with_capacity(half_width * half_height);
-    scaled.extend(input.buf().chunks(stride * 2).take(half_height).flat_map(|pair| {
-        let (top, bot) = pair.split_at(stride);
-        let top = &top[0..half_width * 2];
-        let bot = &bot[0..half_width * 2];
-
-        top.chunks_exact(2)
-            .zip(bot.chunks_exact(2))
-            .map(|(a, b)| (a[0] + a[1] + b[0] + b[1]).mul_add(0.25, a[0].min(a[1]).min(b[0].min(b[1]))) * 0.5)
-    }));
-
-    assert_eq!(half_width * half_height, scaled.len());
-    Img::new(scaled, half_width, half_height)
+    assert!(ImgVec::new(vec![0f32; 7 * 20], 7, 20).downsample().is_none());
 }
 
-#[allow(dead_code)]
-pub(crate) fn avg(input: ImgRef<'_, f32>) -> ImgVec<f32> {
-    let stride = input.stride();
-    let half_height = input.height() / 2;
-    let half_width = input.width() / 2;
-
-    if half_height < 4 || half_width < 4 {
-        return input.new_buf(input.buf().to_vec());
+#[test]
+fn downsample_without_filters() {
+    // Implementations made before the filters only have `downsample()`
+    struct Halved(usize);
+    impl Downsample for Halved {
+        type Output = usize;
+        fn downsample(&self) -> Option<usize> {
+            Some(self.0 / 2)
+        }
     }
-
-    let mut scaled = Vec::with_capacity(half_width * half_height);
-    scaled.extend(input.buf().chunks(stride * 2).take(half_height).flat_map(|pair| {
-        let (top, bot) = pair.split_at(stride);
-        let top = &top[0..half_width * 2];
-        let bot = &bot[0..half_width * 2];
-
-        top.chunks_exact(2).zip(bot.chunks_exact(2)).map(|(a,b)| {
-            (a[0] + a[1] + b[0] + b[1]) * 0.25
-        })
-    }));
-
-    assert_eq!(half_width * half_height, scaled.len());
-    Img::new(scaled, half_width, half_height)
+    assert_eq!(Some(4), Halved(8).downsample_with(DownsampleFilter::Lanczos2));
 }
@@ -5,18 +5,33 @@
 #![allow(clippy::manual_range_contains)]
 #![allow(clippy::new_without_default)]
 
+mod align;
 mod blur;
 mod c_api;
+mod classic;
 mod dssim;
+mod error;
 /// cbindgen:ignore
 mod ffi;
 mod image;
 #[cfg(not(feature = "threads"))]
 mod lieon;
 mod linear;
+mod metrics;
+mod pooling;
+mod resample;
+mod serialize;
+#[cfg(feature = "serde")]
+mod serde_support;
 mod tolab;
 mod val;
+mod viewing;
 
+pub use crate::blur::BlurKernel;
 pub use crate::dssim::*;
+pub use crate::error::Error;
 pub use crate::image::*;
 pub use crate::linear::*;
+pub use crate::metrics::{ErrorMetrics, PixelMetrics};
+pub use crate::pooling::Pooling;
+pub use crate::viewing::ViewingConditions;
@@ -36,6 +36,10 @@ pub trait ParIterLie<T> {
     fn par_iter(&self) -> Self::Iter;
 }
 
+pub trait ParSliceIterLie<T> {
+    fn par_iter(&self) -> std::slice::Iter<'_, T>;
+}
+
 pub trait ParIterMutLie<'a, T> {
     type Iter;
     fn par_iter_mut(&'a mut self) -> Self::Iter;
@@ -73,6 +77,12 @@ impl<'a, T> ParIterLie<T> for &'a [T] {
     }
 }
 
+impl<T> ParSliceIterLie<T> for [T] {
+    fn par_iter(&self) -> std::slice::Iter<'_, T> {
+        self.iter()
+    }
+}
+
 impl<'a, T> ParIterMutLie<'a, T> for &'a mut [T] {
     type Iter = std::slice::IterMut<'a, T>;
 
@@ -88,3 +98,11 @@ impl<T> ParIntoIterLie<T> for Vec<T> {
         self.into_iter()
     }
 }
+
+impl<T> ParIntoIterLie<T> for std::ops::Range<T> where Self: Iterator {
+    type IntoIter = Self;
+
+    fn into_par_iter(self) -> Self::IntoIter {
+        self
+    }
+}
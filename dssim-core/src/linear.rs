@@ -32,7 +32,7 @@ pub trait GammaPixel {
 }
 
 #[inline]
-fn to_linear(s: f32) -> f32 {
+pub(crate) fn to_linear(s: f32) -> f32 {
     if s <= 0.04045 {
         s / 12.92
     } else {
@@ -40,6 +40,78 @@ fn to_linear(s: f32) -> f32 {
     }
 }
 
+/// Inverse of `to_linear`
+#[inline]
+pub(crate) fn to_gamma(l: f32) -> f32 {
+    if l <= 0.0031308 {
+        l * 12.92
+    } else {
+        l.powf(1. / 2.4).mul_add(1.055, -0.055)
+    }
+}
+
+/// Transfer function of HDR pixels. See [`Dssim::create_image_hdr()`][crate::Dssim::create_image_hdr()]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HdrTransfer {
+    /// SMPTE ST 2084 Perceptual Quantizer, used by HDR10
+    Pq,
+    /// Hybrid Log-Gamma (ARIB STD-B67), displayed with 1000 cd/m² peak
+    Hlg,
+}
+
+/// Linear values of HDR images are absolute, and 1.0 is this many cd/m²
+pub(crate) const HDR_MAX_NITS: f32 = 10000.;
+
+/// Reference white of SDR images (ITU-R BT.2408) in HDR linear units
+pub(crate) const SDR_WHITE: f32 = 203. / HDR_MAX_NITS;
+
+const PQ_M1: f32 = 2610. / 16384.;
+const PQ_M2: f32 = 2523. / 4096. * 128.;
+const PQ_C1: f32 = 3424. / 4096.;
+const PQ_C2: f32 = 2413. / 4096. * 32.;
+const PQ_C3: f32 = 2392. / 4096. * 32.;
+
+/// Absolute linear light (1.0 = 10000 cd/m²) to PQ
+#[inline]
+pub(crate) fn pq_encode(l: f32) -> f32 {
+    let l = l.max(0.).powf(PQ_M1);
+    (PQ_C2.mul_add(l, PQ_C1) / PQ_C3.mul_add(l, 1.)).powf(PQ_M2)
+}
+
+/// Inverse of `pq_encode`
+#[inline]
+pub(crate) fn pq_decode(e: f32) -> f32 {
+    let e = e.clamp(0., 1.).powf(1. / PQ_M2);
+    ((e - PQ_C1).max(0.) / PQ_C3.mul_add(-e, PQ_C2)).powf(1. / PQ_M1)
+}
+
+const HLG_A: f32 = 0.17883277;
+const HLG_B: f32 = 1. - 4. * HLG_A;
+const HLG_C: f32 = 0.5599107;
+/// Nominal peak luminance of HLG displays, and the system gamma for it
+const HLG_PEAK: f32 = 1000. / HDR_MAX_NITS;
+const HLG_GAMMA: f32 = 1.2;
+
+/// HLG signal to relative scene light (inverse OETF)
+#[inline]
+fn hlg_to_scene(e: f32) -> f32 {
+    let e = e.clamp(0., 1.);
+    if e <= 0.5 {
+        e * e / 3.
+    } else {
+        (((e - HLG_C) / HLG_A).exp() + HLG_B) / 12.
+    }
+}
+
+/// HLG signal to absolute display light, applying the OOTF with the luminance weights of the primaries (`luma` is the Y row of the RGB to XYZ matrix)
+pub(crate) fn hlg_decode(px: RGBLU, luma: [f32; 3]) -> RGBLU {
+    let scene = RGBLU { r: hlg_to_scene(px.r), g: hlg_to_scene(px.g), b: hlg_to_scene(px.b) };
+    let y = luma[2].mul_add(scene.b, luma[1].mul_add(scene.g, luma[0] * scene.r));
+    let gain = HLG_PEAK * y.max(0.).powf(HLG_GAMMA - 1.);
+    RGBLU { r: scene.r * gain, g: scene.g * gain, b: scene.b * gain }
+}
+
 /// RGBA Premultiplied Linear-light Unit scale
 ///
 /// Convenience function `.to_rgbaplu()` to convert RGBA bitmaps to a format useful for DSSIM.
@@ -196,3 +268,21 @@ impl<P> ToRGBAPLU for [P] where P: GammaPixel<Output=RGBAPLU> {
         self.iter().map(|px| px.to_linear(&gamma_lut).rgb()).collect()
     }
 }
+
+#[test]
+fn hdr_transfer() {
+    assert!((pq_encode(1.) - 1.).abs() < 1e-5);
+    assert!((pq_encode(SDR_WHITE) - 0.58).abs() < 0.005);
+    for i in 0..=100 {
+        let l = (i as f32 / 100.).powi(4);
+        assert!((pq_decode(pq_encode(l)) - l).abs() <= l * 1e-3 + 1e-7, "{l}");
+    }
+    // Peak white of HLG is the nominal display peak
+    let luma = [0.2627, 0.678, 0.0593];
+    let white = hlg_decode(RGBLU { r: 1., g: 1., b: 1. }, luma);
+    assert!((white.g - HLG_PEAK).abs() < 1e-4 && (white.r - white.b).abs() < 1e-6);
+    // Signal 0.75 is the HDR reference white, about 203 cd/m² on a 1000 cd/m² display
+    let ref_white = hlg_decode(RGBLU { r: 0.75, g: 0.75, b: 0.75 }, luma);
+    assert!((ref_white.g - SDR_WHITE).abs() < 0.001, "{ref_white:?}");
+    assert_eq!(0., hlg_decode(RGBLU { r: 0., g: 0., b: 0. }, luma).r);
+}
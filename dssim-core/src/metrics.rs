@@ -0,0 +1,203 @@
+//! Simple per-pixel metrics that are reported next to DSSIM. See [`Dssim::set_pixel_metrics()`][crate::Dssim::set_pixel_metrics()]
+
+use crate::image::RGBLU;
+use crate::linear::to_gamma;
+use crate::tolab::ColorSpace;
+use imgref::*;
+#[cfg(not(feature = "threads"))]
+use crate::lieon as rayon;
+use rayon::prelude::*;
+
+/// Mean squared error and PSNR of R, G, B channels
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorMetrics {
+    /// MSE of R, G, B, with values scaled to 0..1. Multiply by 255² to get MSE of 8-bit values.
+    pub mse: [f64; 3],
+    /// PSNR of R, G, B in dB. It's infinite if the channel is identical.
+    pub psnr: [f64; 3],
+    /// PSNR of all three channels together
+    pub psnr_rgb: f64,
+}
+
+/// Metrics computed from pixels of the full-resolution images. See [`ComparisonReport::pixel_metrics`][crate::ComparisonReport::pixel_metrics]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixelMetrics {
+    /// Errors of gamma-encoded values (using the sRGB curve), the usual MSE/PSNR
+    pub srgb: ErrorMetrics,
+    /// Errors of linear-light RGB values
+    pub linear: ErrorMetrics,
+    /// Average CIEDE2000 ΔE
+    pub ciede2000_mean: f64,
+    /// 95th percentile of CIEDE2000 ΔE, i.e. 5% of pixels have a larger difference
+    pub ciede2000_p95: f64,
+    /// CIEDE2000 ΔE of every pixel
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::map"))]
+    pub ciede2000_map: ImgVec<f32>,
+}
+
+impl ErrorMetrics {
+    fn new(sq_err_sum: [f64; 3], pixels: usize) -> Self {
+        let psnr = |mse: f64| -10. * mse.log10();
+        let mse = sq_err_sum.map(|s| s / pixels as f64);
+        Self {
+            mse,
+            psnr: mse.map(psnr),
+            psnr_rgb: psnr(mse.iter().sum::<f64>() / 3.),
+        }
+    }
+}
+
+/// Both images must have the same size. MSE is computed from the pixel values as they are, and color difference from their actual colors.
+pub(crate) fn pixel_metrics(original: ImgRef<'_, RGBLU>, modified: ImgRef<'_, RGBLU>, original_space: ColorSpace, modified_space: ColorSpace) -> PixelMetrics {
+    let (original_xyz, modified_xyz) = (original_space.to_xyz_matrix(), modified_space.to_xyz_matrix());
+    debug_assert_eq!((original.width(), original.height()), (modified.width(), modified.height()));
+    let (width, height) = (original.width(), original.height());
+
+    let rows: Vec<_> = original.rows().zip(modified.rows()).collect();
+    let per_row: Vec<_> = rows.into_par_iter().map(|(o_row, m_row)| {
+        let mut srgb = [0.; 3];
+        let mut linear = [0.; 3];
+        let delta_e: Vec<f32> = o_row.iter().zip(m_row).map(|(&o, &m)| {
+            let (o_ch, m_ch) = ([o.r, o.g, o.b], [m.r, m.g, m.b]);
+            for c in 0..3 {
+                let lin = f64::from(o_ch[c] - m_ch[c]);
+                let gam = f64::from(to_gamma(o_ch[c]) - to_gamma(m_ch[c]));
+                linear[c] = lin.mul_add(lin, linear[c]);
+                srgb[c] = gam.mul_add(gam, srgb[c]);
+            }
+            ciede2000(lab(o, &original_xyz), lab(m, &modified_xyz)) as f32
+        }).collect();
+        (srgb, linear, delta_e)
+    }).collect();
+
+    let mut srgb = [0.; 3];
+    let mut linear = [0.; 3];
+    let mut map = Vec::with_capacity(width * height);
+    for (s, l, delta_e) in per_row {
+        for c in 0..3 {
+            srgb[c] += s[c];
+            linear[c] += l[c];
+        }
+        map.extend(delta_e);
+    }
+
+    let pixels = width * height;
+    let ciede2000_mean = map.iter().map(|&d| f64::from(d)).sum::<f64>() / pixels as f64;
+    let mut sorted = map.clone();
+    let p95_index = (pixels * 95 / 100).min(pixels - 1);
+    let (_, &mut p95, _) = sorted.select_nth_unstable_by(p95_index, f32::total_cmp);
+
+    PixelMetrics {
+        srgb: ErrorMetrics::new(srgb, pixels),
+        linear: ErrorMetrics::new(linear, pixels),
+        ciede2000_mean,
+        ciede2000_p95: f64::from(p95),
+        ciede2000_map: ImgVec::new(map, width, height),
+    }
+}
+
+/// CIE L\*a\*b\* (D65, L in 0..100) of a linear RGB pixel
+fn lab(px: RGBLU, to_xyz: &[[f32; 3]; 3]) -> [f64; 3] {
+    let [x, y, z] = to_xyz.map(|row| f64::from(row[0]).mul_add(f64::from(px.r), f64::from(row[1]).mul_add(f64::from(px.g), f64::from(row[2]) * f64::from(px.b))));
+    let (x, z) = (x / 0.95047, z / 1.08883);
+    let f = |t: f64| if t > 216. / 24389. { t.cbrt() } else { (24389. / 27. * t + 16.) / 116. };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
+
+/// Color difference ΔE₀₀, as described by Sharma, Wu & Dalal (2005)
+fn ciede2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    use std::f64::consts::PI;
+    let pow7 = |v: f64| v.powi(7);
+    let c_avg = (a1.hypot(b1) + a2.hypot(b2)) / 2.;
+    let g = 0.5 * (1. - (pow7(c_avg) / (pow7(c_avg) + pow7(25.))).sqrt());
+    let (a1, a2) = (a1 * (1. + g), a2 * (1. + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| if a == 0. && b == 0. { 0. } else { b.atan2(a).rem_euclid(2. * PI) };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0. {
+        0.
+    } else if (h2 - h1).abs() <= PI {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 2. * PI
+    } else {
+        h2 - h1 - 2. * PI
+    };
+    let dh = 2. * (c1 * c2).sqrt() * (dh / 2.).sin();
+
+    let l_avg = (l1 + l2) / 2.;
+    let c_avg = (c1 + c2) / 2.;
+    let h_avg = if c1 * c2 == 0. {
+        h1 + h2
+    } else if (h1 - h2).abs() <= PI {
+        (h1 + h2) / 2.
+    } else if h1 + h2 < 2. * PI {
+        (h1 + h2 + 2. * PI) / 2.
+    } else {
+        (h1 + h2 - 2. * PI) / 2.
+    };
+    let t = 1. - 0.17 * (h_avg - PI / 6.).cos() + 0.24 * (2. * h_avg).cos()
+        + 0.32 * (3. * h_avg + PI / 30.).cos() - 0.20 * (4. * h_avg - 63f64.to_radians()).cos();
+    let d_theta = 30f64.to_radians() * (-((h_avg.to_degrees() - 275.) / 25.).powi(2)).exp();
+    let rc = 2. * (pow7(c_avg) / (pow7(c_avg) + pow7(25.))).sqrt();
+    let l50 = (l_avg - 50.).powi(2);
+    let sl = 1. + 0.015 * l50 / (20. + l50).sqrt();
+    let sc = 1. + 0.045 * c_avg;
+    let sh = 1. + 0.015 * c_avg * t;
+    let rt = -(2. * d_theta).sin() * rc;
+
+    let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+    (l * l + c * c + h * h + rt * c * h).sqrt()
+}
+
+#[test]
+fn ciede2000_reference() {
+    // Test data from Sharma, Wu & Dalal
+    let pairs = [
+        ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+        ([50., 3.1571, -77.2803], [50., 0., -82.7485], 2.8615),
+        ([50., -1., 2.], [50., 0., 0.], 2.3669),
+        ([50., 2.49, -0.001], [50., -2.49, 0.0011], 7.2195),
+        ([50., 2.5, 0.], [73., 25., -18.], 27.1492),
+        ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ([22.7233, 20.0904, -46.694], [23.0331, 14.973, -42.5619], 2.0373),
+        ([2.0776, 0.0795, -1.135], [0.9033, -0.0636, -0.5514], 0.9082),
+    ];
+    for (lab1, lab2, expected) in pairs {
+        let de = ciede2000(lab1, lab2);
+        assert!((de - expected).abs() < 1e-4, "{lab1:?} {lab2:?} {de} {expected}");
+        assert!((ciede2000(lab2, lab1) - expected).abs() < 1e-4);
+    }
+    assert!((lab(RGBLU { r: 1., g: 1., b: 1. }, &ColorSpace::Srgb.to_xyz_matrix())[0] - 100.).abs() < 1e-4);
+}
+
+#[test]
+fn errors() {
+    let gray = |v: f32| RGBLU { r: v, g: v, b: v };
+    let original = ImgVec::new(vec![gray(0.5); 100], 10, 10);
+    let mut modified = original.clone();
+    modified.buf_mut()[0].r = 0.;
+    let m = pixel_metrics(original.as_ref(), modified.as_ref(), ColorSpace::Srgb, ColorSpace::Srgb);
+    assert_eq!([0.0025, 0., 0.], m.linear.mse);
+    assert!((m.linear.psnr[0] - 26.0206).abs() < 1e-4);
+    assert!(m.linear.psnr[1].is_infinite());
+    assert!(m.srgb.mse[0] > m.linear.mse[0]);
+    assert!(m.ciede2000_mean > 0. && m.ciede2000_p95 == 0.);
+    assert!(m.ciede2000_map.buf()[0] > 10.);
+
+    let same = pixel_metrics(original.as_ref(), original.as_ref(), ColorSpace::Srgb, ColorSpace::Srgb);
+    assert_eq!(0., same.ciede2000_mean);
+    assert!(same.srgb.psnr_rgb.is_infinite());
+    // Same values, but different colors
+    let p3 = pixel_metrics(original.as_ref(), original.as_ref(), ColorSpace::Srgb, ColorSpace::DisplayP3);
+    assert_eq!(0., p3.srgb.psnr_rgb.recip());
+    assert!(p3.ciede2000_mean < 0.1);
+    let red = ImgVec::new(vec![RGBLU { r: 1., g: 0., b: 0. }; 4], 2, 2);
+    assert!(pixel_metrics(red.as_ref(), red.as_ref(), ColorSpace::Srgb, ColorSpace::Rec2020).ciede2000_mean > 5.);
+}
@@ -0,0 +1,255 @@
+use crate::blur::SMALL_SCALE_SIZE;
+use crate::dssim::DssimChan;
+use crate::error::Error;
+use imgref::*;
+
+/// How per-pixel SSIM scores are combined into a single score of a scale. See [`Dssim::set_pooling()`][crate::Dssim::set_pooling()]
+///
+/// All of them give 1 for identical images. Weight masks apply to all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Pooling {
+    /// Plain average of the SSIM map
+    Mean,
+    /// 1 minus mean absolute deviation from the average SSIM. This is the default.
+    ///
+    /// Smaller scales have the average adjusted to be less sensitive, so that they don't dominate the score.
+    #[default]
+    MeanAbsDeviation,
+    /// 1 minus Minkowski norm of `1 - SSIM`: `1 - (mean((1 - ssim)^p))^(1/p)`.
+    ///
+    /// `p` must be at least 1. Higher values give more importance to the worst pixels.
+    Minkowski(f64),
+    /// SSIM of the pixel at the given percentile, counting from the worst.
+    ///
+    /// `1.0` gives a score that is as bad as the worst 1% of pixels. Must be in `0..=100`.
+    Percentile(f64),
+    /// Average SSIM of the worst square block.
+    ///
+    /// The size is in pixels of the full-resolution image, and is halved at every smaller scale.
+    WorstBlock(usize),
+    /// Average weighted by the amount of information in each area of both images, as in IW-SSIM (Wang & Li).
+    ///
+    /// The weights come from local variances of lightness, so changes in textured and detailed areas count more than in flat ones.
+    /// Only lightness is used, like the grayscale images of IW-SSIM. Chroma is blurred and has little detail of its own,
+    /// so it would mostly repeat the lightness weights, and it's still compared in the SSIM values that are weighted.
+    InformationWeighted,
+}
+
+impl Pooling {
+    pub(crate) fn validate(self) -> Result<Self, Error> {
+        match self {
+            Self::Minkowski(p) if !(p.is_finite() && p >= 1.) => Err(Error::InvalidParameter("Minkowski pooling exponent must be at least 1")),
+            Self::Percentile(p) if !(p >= 0. && p <= 100.) => Err(Error::InvalidParameter("pooling percentile must be in 0..=100 range")),
+            Self::WorstBlock(0) => Err(Error::InvalidParameter("pooling block size must be greater than zero")),
+            _ => Ok(self),
+        }
+    }
+
+    /// Pooling for a scale of the given size. In small-image mode, scales smaller than 8 pixels use the plain average,
+    /// because a uniform change of a tiny image has no deviation from its average.
+    pub(crate) fn for_scale(self, small_images: bool, width: usize, height: usize) -> Self {
+        match self {
+            _ if !small_images || width.min(height) >= SMALL_SCALE_SIZE => self,
+            Self::InformationWeighted => self,
+            _ => Self::Mean,
+        }
+    }
+
+    /// `n` is the scale number, `weights` have the same layout as the map.
+    ///
+    /// For `InformationWeighted` the weights must already include [`information_weights()`].
+    pub(crate) fn pool(self, ssim_map: ImgRef<'_, f32>, n: usize, weights: Option<&[f32]>) -> f64 {
+        debug_assert_eq!(ssim_map.width(), ssim_map.stride());
+        let values = ssim_map.buf();
+        if let Some(weights) = weights {
+            debug_assert_eq!(values.len(), weights.len());
+            if weights.iter().all(|&w| w <= 0.) {
+                return 1.0; // everything excluded, so nothing is different
+            }
+        }
+        match self {
+            Self::Mean | Self::InformationWeighted => weighed_mean(values, weights),
+            Self::MeanAbsDeviation => {
+                let avg = weighed_mean(values, weights).max(0.0).powf((0.5_f64).powf(n as f64));
+                1.0 - weighed_sum(values, weights, |i| (avg - i).abs())
+            },
+            Self::Minkowski(p) => {
+                1.0 - weighed_sum(values, weights, |i| (1.0 - i).abs().powf(p)).powf(1. / p)
+            },
+            Self::Percentile(p) => percentile(values, weights, p / 100.),
+            Self::WorstBlock(size) => worst_block(ssim_map, weights, (size >> n).max(1)),
+        }
+    }
+}
+
+const HISTOGRAM_BINS: usize = 1 << 16;
+
+/// SSIM values of a scale collected in fine-grained bins, for pooling maps that are too large to keep in memory.
+///
+/// Values in the same bin are treated as if they were all equal to their average.
+pub(crate) struct Histogram {
+    count: Vec<u64>,
+    sum: Vec<f64>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self { count: vec![0; HISTOGRAM_BINS], sum: vec![0.; HISTOGRAM_BINS] }
+    }
+
+    pub fn add(&mut self, values: &[f32]) {
+        for &v in values {
+            // SSIM is in -1..=1 range
+            let bin = (((v + 1.) * (HISTOGRAM_BINS / 2) as f32) as usize).min(HISTOGRAM_BINS - 1);
+            self.count[bin] += 1;
+            self.sum[bin] += f64::from(v);
+        }
+    }
+
+    /// Same as [`Pooling::pool()`], except `WorstBlock` and `InformationWeighted` which need pixel positions
+    pub fn pool(&self, pooling: Pooling, n: usize) -> f64 {
+        let total = self.count.iter().sum::<u64>() as f64;
+        if total == 0. {
+            return 1.0;
+        }
+        let bins = || self.count.iter().zip(&self.sum).filter(|&(&c, _)| c > 0).map(|(&c, &s)| (c as f64, s));
+        let mean = self.sum.iter().sum::<f64>() / total;
+        match pooling {
+            Pooling::Mean => mean,
+            Pooling::MeanAbsDeviation => {
+                let avg = mean.max(0.0).powf((0.5_f64).powf(n as f64));
+                1.0 - bins().map(|(c, s)| c.mul_add(avg, -s).abs()).sum::<f64>() / total
+            },
+            Pooling::Minkowski(p) => {
+                1.0 - (bins().map(|(c, s)| c * (1.0 - s / c).abs().powf(p)).sum::<f64>() / total).powf(1. / p)
+            },
+            Pooling::Percentile(p) => {
+                let limit = ((p / 100. * total).ceil()).max(1.);
+                let mut acc = 0.;
+                bins().find(|&(c, _)| {
+                    acc += c;
+                    acc >= limit
+                }).map_or(1.0, |(c, s)| s / c)
+            },
+            Pooling::WorstBlock(_) | Pooling::InformationWeighted => unreachable!("not supported for histograms"),
+        }
+    }
+}
+
+/// Flat areas still count a little, so that images without any texture can be compared
+const MIN_INFORMATION: f32 = 1e-3;
+
+/// Pooling weights for [`Pooling::InformationWeighted`]: bits of information in local areas of both images,
+/// `log2((1 + σ₁²/noise) · (1 + σ₂²/noise))`, multiplied by the weight mask if there is one.
+///
+/// The channels are the lightness channels of both images. Chroma channels are deliberately not used.
+pub(crate) fn information_weights(original: &DssimChan<f32>, modified: &DssimChan<f32>, noise: f32, mask: Option<&[f32]>) -> Vec<f32> {
+    let variance = |chan: &DssimChan<f32>, i: usize| (chan.img_sq_blur[i] - chan.mu[i] * chan.mu[i]).max(0.);
+    (0..original.mu.len()).map(|i| {
+        let information = ((1. + variance(original, i) / noise) * (1. + variance(modified, i) / noise)).log2() + MIN_INFORMATION;
+        mask.map_or(information, |mask| information * mask[i])
+    }).collect()
+}
+
+pub(crate) fn weighed_mean(values: &[f32], weights: Option<&[f32]>) -> f64 {
+    weighed_sum(values, weights, |i| i)
+}
+
+/// Weighed average of `f(value)`
+fn weighed_sum(values: &[f32], weights: Option<&[f32]>, f: impl Fn(f64) -> f64) -> f64 {
+    if let Some(weights) = weights {
+        let (sum, weight_sum) = values.iter().zip(weights).fold((0., 0.), |(sum, weight_sum), (&i, &w)| {
+            (f64::from(w).mul_add(f(f64::from(i)), sum), weight_sum + f64::from(w))
+        });
+        if weight_sum > 0. { sum / weight_sum } else { f(1.0) }
+    } else {
+        values.iter().fold(0., |sum, &i| sum + f(f64::from(i))) / values.len() as f64
+    }
+}
+
+fn percentile(values: &[f32], weights: Option<&[f32]>, fraction: f64) -> f64 {
+    if let Some(weights) = weights {
+        let mut weighed: Vec<_> = values.iter().copied().zip(weights.iter().copied()).filter(|&(_, w)| w > 0.).collect();
+        weighed.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let limit = fraction * weighed.iter().map(|&(_, w)| f64::from(w)).sum::<f64>();
+        let mut acc = 0.;
+        for &(v, w) in &weighed {
+            acc += f64::from(w);
+            if acc >= limit {
+                return f64::from(v);
+            }
+        }
+        weighed.last().map_or(1.0, |&(v, _)| f64::from(v))
+    } else {
+        let mut sorted = values.to_vec();
+        let nth = ((fraction * sorted.len() as f64).ceil() as usize).saturating_sub(1).min(sorted.len() - 1);
+        let (_, v, _) = sorted.select_nth_unstable_by(nth, f32::total_cmp);
+        f64::from(*v)
+    }
+}
+
+fn worst_block(ssim_map: ImgRef<'_, f32>, weights: Option<&[f32]>, size: usize) -> f64 {
+    let width = ssim_map.width();
+    let mut worst = f64::INFINITY;
+    for y in (0..ssim_map.height()).step_by(size) {
+        let block_height = size.min(ssim_map.height() - y);
+        for x in (0..width).step_by(size) {
+            let block_width = size.min(width - x);
+            let mut sum = 0.;
+            let mut weight_sum = 0.;
+            for row in y..y + block_height {
+                let start = row * width + x;
+                for i in start..start + block_width {
+                    let w = weights.map_or(1., |w| f64::from(w[i]));
+                    sum = w.mul_add(f64::from(ssim_map.buf()[i]), sum);
+                    weight_sum += w;
+                }
+            }
+            if weight_sum > 0. {
+                worst = worst.min(sum / weight_sum);
+            }
+        }
+    }
+    if worst.is_finite() { worst } else { 1.0 }
+}
+
+#[test]
+fn pooling_types() {
+    let map = ImgVec::new((0..100).map(|i| 1. - i as f32 / 1000.).collect(), 10, 10);
+    let mean = Pooling::Mean.pool(map.as_ref(), 0, None);
+    assert!((mean - 0.9505).abs() < 1e-6, "{mean}");
+    assert!(Pooling::MeanAbsDeviation.pool(map.as_ref(), 0, None) > 0.97);
+    let l1 = Pooling::Minkowski(1.).pool(map.as_ref(), 0, None);
+    let l4 = Pooling::Minkowski(4.).pool(map.as_ref(), 0, None);
+    assert!((l1 - mean).abs() < 1e-6 && l4 < l1, "{l1} {l4}");
+    let p = Pooling::Percentile(1.).pool(map.as_ref(), 0, None);
+    assert!((p - 0.901).abs() < 1e-6, "{p}");
+    let p = Pooling::Percentile(50.).pool(map.as_ref(), 0, None);
+    assert!((p - 0.95).abs() < 1e-6, "{p}");
+    // 2x2 in the bottom-right corner
+    let b = Pooling::WorstBlock(4).pool(map.as_ref(), 1, None);
+    assert!((b - (1. - 0.0935)).abs() < 1e-6, "{b}");
+
+    let mut weights = vec![0.; 100];
+    weights[..10].fill(1.);
+    for p in [Pooling::Mean, Pooling::Minkowski(2.), Pooling::Percentile(100.), Pooling::WorstBlock(2)] {
+        let v = p.pool(map.as_ref(), 0, Some(&weights));
+        assert!(v >= 0.991 - 1e-6, "{p:?} {v}");
+    }
+    assert_eq!(1., Pooling::Mean.pool(map.as_ref(), 0, Some(&[0.; 100])));
+
+    assert!(Pooling::Minkowski(0.5).validate().is_err());
+    assert!(Pooling::Percentile(101.).validate().is_err());
+    assert!(Pooling::WorstBlock(0).validate().is_err());
+
+    let mut hist = Histogram::new();
+    hist.add(&map.buf()[..50]);
+    hist.add(&map.buf()[50..]);
+    for p in [Pooling::Mean, Pooling::MeanAbsDeviation, Pooling::Minkowski(3.), Pooling::Percentile(1.), Pooling::Percentile(50.)] {
+        let expected = p.pool(map.as_ref(), 1, None);
+        let got = hist.pool(p, 1);
+        assert!((expected - got).abs() < 1e-5, "{p:?} {expected} {got}");
+    }
+}
@@ -0,0 +1,117 @@
+use crate::image::MixAdd;
+use imgref::*;
+
+/// Contributing source pixels and their weights for every destination pixel
+fn tent_weights(src_len: usize, dst_len: usize) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_len as f64 / dst_len as f64;
+    // when shrinking, the filter is widened to cover all source pixels
+    let radius = scale.max(1.);
+    (0..dst_len).map(|x| {
+        let center = (x as f64 + 0.5).mul_add(scale, -0.5);
+        let start = (center - radius).floor() as isize + 1;
+        let end = (center + radius).ceil() as isize;
+        let mut taps: Vec<(usize, f32)> = (start..end).filter_map(|i| {
+            let w = 1. - (i as f64 - center).abs() / radius;
+            // out-of-bounds pixels are replaced by the edge pixels
+            (w > 0.).then(|| (i.clamp(0, src_len as isize - 1) as usize, w as f32))
+        }).collect();
+        let sum: f32 = taps.iter().map(|&(_, w)| w).sum();
+        taps.iter_mut().for_each(|(_, w)| *w /= sum);
+        taps
+    }).collect()
+}
+
+/// Resize with a triangle filter. Pixels must be in linear light.
+pub(crate) fn resample<T: MixAdd>(img: ImgRef<'_, T>, width: usize, height: usize) -> ImgVec<T> {
+    debug_assert!(width > 0 && height > 0 && img.width() > 0 && img.height() > 0);
+    let horiz = tent_weights(img.width(), width);
+    let vert = tent_weights(img.height(), height);
+
+    let mut tmp = Vec::with_capacity(width * img.height());
+    for row in img.rows() {
+        tmp.extend(horiz.iter().map(|taps| {
+            taps.iter().fold(T::default(), |acc, &(x, w)| acc.mix_add(row[x], w))
+        }));
+    }
+
+    let mut out = Vec::with_capacity(width * height);
+    for taps in &vert {
+        out.extend((0..width).map(|x| {
+            taps.iter().fold(T::default(), |acc, &(y, w)| acc.mix_add(tmp[y * width + x], w))
+        }));
+    }
+    ImgVec::new(out, width, height)
+}
+
+/// Scale to half the size, rounded up, with a filter of even number of `taps` centered between every two source pixels.
+///
+/// Out-of-bounds pixels are replaced by the edge pixels.
+pub(crate) fn halve<T: MixAdd>(img: ImgRef<'_, T>, taps: &[f32]) -> ImgVec<T> {
+    debug_assert!(taps.len().is_multiple_of(2));
+    let (width, height) = (img.width().div_ceil(2), img.height().div_ceil(2));
+    let offset = taps.len() / 2 - 1;
+    let sample = |len: usize, pos: usize, get: &dyn Fn(usize) -> T| {
+        taps.iter().enumerate().fold(T::default(), |acc, (i, &w)| {
+            acc.mix_add(get((2 * pos + i).saturating_sub(offset).min(len - 1)), w)
+        })
+    };
+
+    let mut tmp = Vec::with_capacity(width * img.height());
+    for row in img.rows() {
+        tmp.extend((0..width).map(|x| sample(row.len(), x, &|i| row[i])));
+    }
+
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        out.extend((0..width).map(|x| sample(img.height(), y, &|i| tmp[i * width + x])));
+    }
+    ImgVec::new(out, width, height)
+}
+
+/// Bilinear sampling of a `width`×`height` area that starts at a fractional position `x`,`y`.
+///
+/// The area should fit in the image. Out-of-bounds pixels are replaced by the edge pixels.
+pub(crate) fn translate<T: MixAdd>(img: ImgRef<'_, T>, x: f64, y: f64, width: usize, height: usize) -> ImgVec<T> {
+    let taps = |pos: f64, len: usize| {
+        let start = pos.floor();
+        let frac = (pos - start) as f32;
+        let i0 = (start.max(0.) as usize).min(len - 1);
+        (i0, (i0 + 1).min(len - 1), frac)
+    };
+    let cols: Vec<_> = (0..width).map(|i| taps(x + i as f64, img.width())).collect();
+
+    let mut out = Vec::with_capacity(width * height);
+    for j in 0..height {
+        let (y0, y1, fy) = taps(y + j as f64, img.height());
+        let (row0, row1) = (&img[y0], &img[y1]);
+        out.extend(cols.iter().map(|&(x0, x1, fx)| {
+            let top = T::default().mix_add(row0[x0], 1. - fx).mix_add(row0[x1], fx);
+            let bottom = T::default().mix_add(row1[x0], 1. - fx).mix_add(row1[x1], fx);
+            T::default().mix_add(top, 1. - fy).mix_add(bottom, fy)
+        }));
+    }
+    ImgVec::new(out, width, height)
+}
+
+#[test]
+fn resample_sizes() {
+    let img = ImgVec::new((0..12).map(|i| i as f32).collect(), 4, 3);
+    let same = resample(img.as_ref(), 4, 3);
+    assert_eq!(img.buf(), same.buf());
+
+    let flat = resample(ImgVec::new(vec![0.5f32; 7 * 5], 7, 5).as_ref(), 3, 11);
+    assert_eq!((3, 11), (flat.width(), flat.height()));
+    assert!(flat.pixels().all(|p| (p - 0.5).abs() < 1e-6));
+
+    let half = resample(img.as_ref(), 2, 3);
+    assert!((half.buf()[0] - 0.625).abs() < 1e-6, "{:?}", half.buf());
+}
+
+#[test]
+fn translate_half() {
+    let img = ImgVec::new((0..20).map(|i| i as f32).collect(), 5, 4);
+    let same = translate(img.as_ref(), 0., 0., 5, 4);
+    assert_eq!(img.buf(), same.buf());
+    let shifted = translate(img.as_ref(), 1.5, 1., 3, 2);
+    assert_eq!(&[6.5, 7.5, 8.5, 11.5, 12.5, 13.5], &shifted.buf()[..]);
+}
@@ -0,0 +1,68 @@
+use crate::blur::BlurKernel;
+use crate::dssim::AlphaMode;
+use crate::pooling::Pooling;
+use crate::tolab::ColorSpace;
+use imgref::*;
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+/// Maps are stored compactly as their size and a flat array of pixels
+pub(crate) mod map {
+    use super::*;
+    use serde::{Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Map<P> {
+        width: usize,
+        height: usize,
+        pixels: P,
+    }
+
+    pub fn serialize<S: Serializer>(img: &ImgVec<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        let pixels: Vec<f32> = img.pixels().collect();
+        Map { width: img.width(), height: img.height(), pixels }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ImgVec<f32>, D::Error> {
+        let Map { width, height, pixels } = Map::<Vec<f32>>::deserialize(deserializer)?;
+        if width.checked_mul(height) != Some(pixels.len()) || width == 0 {
+            return Err(D::Error::custom("map size doesn't match the number of pixels"));
+        }
+        Ok(ImgVec::new(pixels, width, height))
+    }
+}
+
+/// Same checks as `Dssim::set_pooling()`
+pub(crate) fn validated_pooling<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pooling, D::Error> {
+    Pooling::deserialize(deserializer)?.validate().map_err(D::Error::custom)
+}
+
+/// Same checks as `Dssim::set_ssim_constants()`
+pub(crate) fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let v = f64::deserialize(deserializer)?;
+    if v.is_finite() && v > 0. { Ok(v) } else { Err(D::Error::custom("SSIM constants must be finite and greater than zero")) }
+}
+
+/// Same checks as `Dssim::set_blur_kernel()`
+pub(crate) fn validated_blur_kernel<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlurKernel, D::Error> {
+    BlurKernel::deserialize(deserializer)?.validate().map_err(D::Error::custom)
+}
+
+/// Same checks as `Dssim::set_color_space()`
+pub(crate) fn validated_color_space<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ColorSpace, D::Error> {
+    ColorSpace::deserialize(deserializer)?.validate().map_err(D::Error::custom)
+}
+
+/// Same checks as `Dssim::set_channel_weights()`
+pub(crate) fn validated_channel_weights<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[f64; 3], D::Error> {
+    crate::dssim::validate_channel_weights(<[f64; 3]>::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+/// Same checks as `Dssim::set_chroma_blur()`
+pub(crate) fn validated_chroma_blur<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    crate::dssim::validate_chroma_blur(f64::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+/// Same checks as `Dssim::set_alpha_mode()`
+pub(crate) fn validated_alpha_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AlphaMode, D::Error> {
+    AlphaMode::deserialize(deserializer)?.validate().map_err(D::Error::custom)
+}
@@ -0,0 +1,373 @@
+use crate::blur::BlurKernel;
+use crate::dssim::{Algorithm, AlgorithmVersion, AlphaMode, DssimChan, DssimChanScale, DssimImage};
+use crate::error::Error;
+use crate::image::{DownsampleFilter, RGBLU};
+use crate::tolab::{ColorModel, ColorSpace};
+use imgref::*;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 8] = b"DSSIMIMG";
+
+/// Must be increased whenever preprocessing of images changes, or the layout changes
+const FORMAT_VERSION: u32 = 1;
+
+impl DssimImage<f32> {
+    /// Save the preprocessed image, so that it can be compared again later without decoding and preprocessing it again.
+    ///
+    /// All scales are saved, as well as the weight mask and pixels for [`PixelMetrics`][crate::PixelMetrics], if any. The data is uncompressed,
+    /// and takes about 16 bytes per pixel for color images. Use a buffered writer.
+    ///
+    /// Fails for images created with [`ColorModel::Custom`].
+    pub fn write(&self, mut writer: impl Write) -> Result<(), Error> {
+        let color_model = match self.color_model {
+            ColorModel::Lab => 0,
+            ColorModel::Oklab => 1,
+            ColorModel::YCbCr => 2,
+            ColorModel::Custom(_) => return Err(Error::InvalidParameter("images with a custom color model can't be saved")),
+        };
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, FORMAT_VERSION)?;
+        let (kernel_type, sigma) = match self.blur_kernel {
+            BlurKernel::Default => (0, 0.),
+            BlurKernel::Gaussian { sigma } => (1, sigma),
+        };
+        writer.write_all(&[kernel_type])?;
+        writer.write_all(&sigma.to_le_bytes())?;
+        writer.write_all(&[match self.algorithm {
+            Algorithm::Dssim => 0,
+            Algorithm::Ssim => 1,
+            Algorithm::MsSsim => 2,
+        }])?;
+        writer.write_all(&[u8::from(self.hdr)])?;
+        writer.write_all(&[color_model])?;
+        writer.write_all(&[self.chroma_blur])?;
+        writer.write_all(&[match self.downsample_filter {
+            DownsampleFilter::Box => 0,
+            DownsampleFilter::Triangle => 1,
+            DownsampleFilter::Lanczos2 => 2,
+            DownsampleFilter::BoxTruncating => 3,
+        }])?;
+        writer.write_all(&[u8::from(self.small_images)])?;
+        writer.write_all(&[match self.algorithm_version {
+            None => 0,
+            Some(AlgorithmVersion::V3_5) => 1,
+            Some(AlgorithmVersion::V4_0) => 2,
+        }])?;
+        write_len(&mut writer, self.scale.len())?;
+        for s in &self.scale {
+            write_len(&mut writer, s.chan.len())?;
+            write_len(&mut writer, s.chan[0].width)?;
+            write_len(&mut writer, s.chan[0].height)?;
+            for ch in &s.chan {
+                write_chan(&mut writer, ch)?;
+            }
+        }
+        if let Some(masks) = &self.weight_mask {
+            writer.write_all(&[1])?;
+            for m in masks {
+                write_f32s(&mut writer, m.buf())?;
+            }
+        } else {
+            writer.write_all(&[0])?;
+        }
+        match self.alpha_mode {
+            AlphaMode::Pattern => writer.write_all(&[0])?,
+            AlphaMode::Background(c) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&c)?;
+            },
+            AlphaMode::Checkerboard { size, light, dark } => {
+                writer.write_all(&[2])?;
+                writer.write_all(&size.to_le_bytes())?;
+                writer.write_all(&light)?;
+                writer.write_all(&dark)?;
+            },
+            AlphaMode::Separate { weight } => {
+                writer.write_all(&[3])?;
+                writer.write_all(&weight.to_le_bytes())?;
+            },
+        }
+        if let Some(alpha) = &self.alpha {
+            writer.write_all(&[1])?;
+            for ch in alpha {
+                write_chan(&mut writer, ch)?;
+            }
+        } else {
+            writer.write_all(&[0])?;
+        }
+        if let Some(pixels) = &self.pixels {
+            writer.write_all(&[1])?;
+            let (space_type, matrix) = match self.color_space {
+                ColorSpace::Srgb => (0, None),
+                ColorSpace::DisplayP3 => (1, None),
+                ColorSpace::Rec2020 => (2, None),
+                ColorSpace::AdobeRgb => (3, None),
+                ColorSpace::Custom(m) => (4, Some(m)),
+            };
+            writer.write_all(&[space_type])?;
+            if let Some(m) = matrix {
+                write_f32s(&mut writer, m.as_flattened())?;
+            }
+            let rgb: Vec<f32> = pixels.pixels().flat_map(|px| [px.r, px.g, px.b]).collect();
+            write_f32s(&mut writer, &rgb)?;
+        } else {
+            writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// Load an image saved with [`DssimImage::write()`].
+    ///
+    /// Fails if the data has been written by a version of DSSIM that processes images differently.
+    /// Use a buffered reader.
+    pub fn read(mut reader: impl Read) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidData("not a DSSIM image"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(Error::IncompatibleVersion { expected: FORMAT_VERSION, found: version });
+        }
+
+        let mut kernel_type = [0];
+        reader.read_exact(&mut kernel_type)?;
+        let mut sigma = [0; 8];
+        reader.read_exact(&mut sigma)?;
+        let blur_kernel = match kernel_type[0] {
+            0 => BlurKernel::Default,
+            1 => BlurKernel::Gaussian { sigma: f64::from_le_bytes(sigma) }.validate().map_err(|_| Error::InvalidData("invalid blur kernel"))?,
+            _ => return Err(Error::InvalidData("unknown blur kernel")),
+        };
+        let mut algorithm = [0];
+        reader.read_exact(&mut algorithm)?;
+        let algorithm = match algorithm[0] {
+            0 => Algorithm::Dssim,
+            1 => Algorithm::Ssim,
+            2 => Algorithm::MsSsim,
+            _ => return Err(Error::InvalidData("unknown algorithm")),
+        };
+        let mut hdr = [0];
+        reader.read_exact(&mut hdr)?;
+        let mut color_model = [0];
+        reader.read_exact(&mut color_model)?;
+        let color_model = match color_model[0] {
+            0 => ColorModel::Lab,
+            1 => ColorModel::Oklab,
+            2 => ColorModel::YCbCr,
+            _ => return Err(Error::InvalidData("unknown color model")),
+        };
+        let mut chroma_blur = [0];
+        reader.read_exact(&mut chroma_blur)?;
+        let mut downsample_filter = [0];
+        reader.read_exact(&mut downsample_filter)?;
+        let downsample_filter = match downsample_filter[0] {
+            0 => DownsampleFilter::Box,
+            1 => DownsampleFilter::Triangle,
+            2 => DownsampleFilter::Lanczos2,
+            3 => DownsampleFilter::BoxTruncating,
+            _ => return Err(Error::InvalidData("unknown downsample filter")),
+        };
+        let mut small_images = [0];
+        reader.read_exact(&mut small_images)?;
+        let mut algorithm_version = [0];
+        reader.read_exact(&mut algorithm_version)?;
+        let algorithm_version = match algorithm_version[0] {
+            0 => None,
+            1 => Some(AlgorithmVersion::V3_5),
+            2 => Some(AlgorithmVersion::V4_0),
+            _ => return Err(Error::InvalidData("unknown algorithm version")),
+        };
+
+        let num_scales = read_u32(&mut reader)? as usize;
+        if num_scales == 0 || num_scales > 64 {
+            return Err(Error::InvalidData("invalid number of scales"));
+        }
+        let mut scale = Vec::with_capacity(num_scales);
+        for _ in 0..num_scales {
+            let num_chan = read_u32(&mut reader)? as usize;
+            let width = read_u32(&mut reader)? as usize;
+            let height = read_u32(&mut reader)? as usize;
+            if !matches!(num_chan, 1 | 3) {
+                return Err(Error::InvalidData("invalid number of channels"));
+            }
+            if width.checked_mul(height).is_none_or(|p| p == 0) {
+                return Err(Error::InvalidData("invalid size"));
+            }
+            let chan = (0..num_chan).map(|_| read_chan(&mut reader, width, height)).collect::<Result<_, Error>>()?;
+            scale.push(DssimChanScale { chan });
+        }
+
+        let mut has_mask = [0];
+        reader.read_exact(&mut has_mask)?;
+        let weight_mask = if has_mask[0] != 0 {
+            Some(scale.iter().map(|s| {
+                let (width, height) = (s.chan[0].width, s.chan[0].height);
+                Ok(ImgVec::new(read_f32s(&mut reader, width * height)?, width, height))
+            }).collect::<Result<_, Error>>()?)
+        } else {
+            None
+        };
+
+        let mut alpha_type = [0];
+        reader.read_exact(&mut alpha_type)?;
+        let alpha_mode = match alpha_type[0] {
+            0 => AlphaMode::Pattern,
+            1 => {
+                let mut c = [0; 3];
+                reader.read_exact(&mut c)?;
+                AlphaMode::Background(c)
+            },
+            2 => {
+                let mut size = [0; 2];
+                let (mut light, mut dark) = ([0; 3], [0; 3]);
+                reader.read_exact(&mut size)?;
+                reader.read_exact(&mut light)?;
+                reader.read_exact(&mut dark)?;
+                AlphaMode::Checkerboard { size: u16::from_le_bytes(size), light, dark }
+            },
+            3 => {
+                let mut weight = [0; 8];
+                reader.read_exact(&mut weight)?;
+                AlphaMode::Separate { weight: f64::from_le_bytes(weight) }
+            },
+            _ => return Err(Error::InvalidData("unknown alpha mode")),
+        }.validate().map_err(|_| Error::InvalidData("invalid alpha mode"))?;
+        let mut has_alpha = [0];
+        reader.read_exact(&mut has_alpha)?;
+        let alpha = if has_alpha[0] != 0 {
+            Some(scale.iter().map(|s| read_chan(&mut reader, s.chan[0].width, s.chan[0].height)).collect::<Result<_, Error>>()?)
+        } else {
+            None
+        };
+
+        let mut has_pixels = [0];
+        reader.read_exact(&mut has_pixels)?;
+        let mut color_space = ColorSpace::Srgb;
+        let pixels = if has_pixels[0] != 0 {
+            let mut space_type = [0];
+            reader.read_exact(&mut space_type)?;
+            color_space = match space_type[0] {
+                0 => ColorSpace::Srgb,
+                1 => ColorSpace::DisplayP3,
+                2 => ColorSpace::Rec2020,
+                3 => ColorSpace::AdobeRgb,
+                4 => {
+                    let m = read_f32s(&mut reader, 9)?;
+                    ColorSpace::Custom([[m[0], m[1], m[2]], [m[3], m[4], m[5]], [m[6], m[7], m[8]]])
+                        .validate().map_err(|_| Error::InvalidData("invalid color space"))?
+                },
+                _ => return Err(Error::InvalidData("unknown color space")),
+            };
+            let (width, height) = (scale[0].chan[0].width, scale[0].chan[0].height);
+            let rgb = read_f32s(&mut reader, width * height * 3)?;
+            Some(ImgVec::new(rgb.chunks_exact(3).map(|c| RGBLU { r: c[0], g: c[1], b: c[2] }).collect(), width, height))
+        } else {
+            None
+        };
+
+        Ok(Self { scale, weight_mask, blur_kernel, algorithm, pixels, color_space, color_model, chroma_blur: chroma_blur[0], hdr: hdr[0] != 0, alpha_mode, alpha, downsample_filter, small_images: small_images[0] != 0, algorithm_version })
+    }
+}
+
+fn write_chan(writer: &mut impl Write, ch: &DssimChan<f32>) -> Result<(), Error> {
+    let img = ch.img.as_ref().ok_or(Error::InvalidData("image has been discarded"))?;
+    writer.write_all(&[u8::from(ch.is_chroma)])?;
+    write_f32s(writer, img.buf())?;
+    write_f32s(writer, &ch.mu)?;
+    write_f32s(writer, &ch.img_sq_blur)?;
+    Ok(())
+}
+
+fn read_chan(reader: &mut impl Read, width: usize, height: usize) -> Result<DssimChan<f32>, Error> {
+    let pixels = width * height;
+    let mut is_chroma = [0];
+    reader.read_exact(&mut is_chroma)?;
+    Ok(DssimChan {
+        width,
+        height,
+        img: Some(ImgVec::new(read_f32s(reader, pixels)?, width, height)),
+        mu: read_f32s(reader, pixels)?,
+        img_sq_blur: read_f32s(reader, pixels)?,
+        is_chroma: is_chroma[0] != 0,
+    })
+}
+
+fn write_u32(writer: &mut impl Write, val: u32) -> Result<(), Error> {
+    writer.write_all(&val.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> Result<(), Error> {
+    write_u32(writer, u32::try_from(len).map_err(|_| Error::InvalidData("image is too large to be saved"))?)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn write_f32s(writer: &mut impl Write, values: &[f32]) -> Result<(), Error> {
+    let mut buf = [0; 4096];
+    for chunk in values.chunks(buf.len() / 4) {
+        for (b, v) in buf.chunks_exact_mut(4).zip(chunk) {
+            b.copy_from_slice(&v.to_le_bytes());
+        }
+        writer.write_all(&buf[..chunk.len() * 4])?;
+    }
+    Ok(())
+}
+
+/// Reads in chunks, so that a corrupted size can't allocate more memory than there is data
+fn read_f32s(reader: &mut impl Read, len: usize) -> Result<Vec<f32>, Error> {
+    let mut out = Vec::with_capacity(len.min(1 << 20));
+    let mut buf = [0; 4096];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(buf.len() / 4);
+        reader.read_exact(&mut buf[..n * 4])?;
+        out.extend(buf[..n * 4].chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        left -= n;
+    }
+    Ok(out)
+}
+
+#[test]
+fn roundtrip() {
+    let d = crate::new();
+    let (rgba1, rgba2) = crate::dssim::test_images();
+    let (w, h) = (rgba1.width(), rgba1.height());
+    let mut img1 = d.create_image_rgba(rgba1.buf(), w, h).unwrap();
+    img1.set_weight_mask(ImgVec::new((0..w * h).map(|i| (i % 7) as f32).collect(), w, h).as_ref()).unwrap();
+    let img2 = d.create_image_rgba(rgba2.buf(), w, h).unwrap();
+
+    let mut data = Vec::new();
+    img1.write(&mut data).unwrap();
+    let loaded = DssimImage::read(&data[..]).unwrap();
+    assert_eq!((w, h), (loaded.width(), loaded.height()));
+    assert_eq!(d.compare(&img1, &img2).unwrap().0, d.compare(&loaded, &img2).unwrap().0);
+    assert_eq!(Some(AlgorithmVersion::LATEST), loaded.algorithm_version);
+
+    let gray = d.create_image(&ImgVec::new(vec![0.5f32; 64], 8, 8)).unwrap();
+    let mut gray_data = Vec::new();
+    gray.write(&mut gray_data).unwrap();
+    assert_eq!(0., d.compare(&gray, DssimImage::read(&gray_data[..]).unwrap()).unwrap().0);
+
+    let mut d2 = crate::new();
+    d2.set_blur_kernel(BlurKernel::Gaussian { sigma: 0.7 }).unwrap();
+    let blurred = d2.create_image_rgba(rgba2.buf(), w, h).unwrap();
+    let mut blurred_data = Vec::new();
+    blurred.write(&mut blurred_data).unwrap();
+    let loaded = DssimImage::read(&blurred_data[..]).unwrap();
+    assert_eq!(BlurKernel::Gaussian { sigma: 0.7 }, loaded.blur_kernel);
+    assert!(d.compare(&img1, &loaded).is_err());
+
+    assert_eq!(Some(Error::Io(std::io::ErrorKind::UnexpectedEof)), DssimImage::read(&data[..data.len() - 1]).err());
+    assert_eq!(Some(Error::InvalidData("not a DSSIM image")), DssimImage::read(&b"PNG............."[..]).err());
+    data[8] = 0;
+    assert_eq!(Some(Error::IncompatibleVersion { expected: FORMAT_VERSION, found: 0 }), DssimImage::read(&data[..]).err());
+    #[cfg(target_pointer_width = "64")]
+    assert!(write_len(&mut Vec::new(), 1 << 32).is_err());
+}
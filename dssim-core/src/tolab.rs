@@ -1,10 +1,13 @@
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
 
-use crate::image::ToRGB;
+use crate::image::{Downsample, DownsampleFilter, ToRGB};
 use crate::image::RGBAPLU;
 use crate::image::RGBLU;
+use crate::linear::{pq_encode, to_gamma, SDR_WHITE};
 use imgref::*;
+use std::fmt;
+use std::sync::Arc;
 #[cfg(not(feature = "threads"))]
 use crate::lieon as rayon;
 use rayon::prelude::*;
@@ -14,6 +17,181 @@ const D65y: f32 = 1.0;
 const D65z: f32 = 1.089;
 
 pub type GBitmap = ImgVec<f32>;
+
+/// Primaries of linear RGB pixels. See [`Dssim::set_color_space()`][crate::Dssim::set_color_space()]
+///
+/// All of them use the D65 white point. Colors outside of the sRGB gamut are compared without clipping.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ColorSpace {
+    /// sRGB and Rec.709. This is the default.
+    #[default]
+    Srgb,
+    /// Display P3 (DCI-P3 primaries with the D65 white point)
+    DisplayP3,
+    /// Rec.2020 and Rec.2100
+    Rec2020,
+    /// Adobe RGB (1998)
+    AdobeRgb,
+    /// Matrix converting linear RGB to CIE XYZ, in rows of X, Y and Z.
+    ///
+    /// White (1, 1, 1) should be converted to D65 white with Y = 1.
+    Custom([[f32; 3]; 3]),
+}
+
+impl ColorSpace {
+    pub(crate) fn validate(self) -> Result<Self, crate::Error> {
+        if let Self::Custom(m) = self {
+            let white_y = m[1].iter().sum::<f32>();
+            if !(m.iter().flatten().all(|v| v.is_finite()) && white_y > 0.5 && white_y < 2.) {
+                return Err(crate::Error::InvalidParameter("color space matrix must be finite, and convert white to Y = 1"));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Linear RGB to CIE XYZ
+    pub(crate) fn to_xyz_matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Srgb => [
+                [0.4124564, 0.3575761, 0.1804375],
+                [0.2126729, 0.7151522, 0.072175],
+                [0.0193339, 0.119192, 0.9503041],
+            ],
+            Self::DisplayP3 => [
+                [0.4865709, 0.2656677, 0.1982173],
+                [0.2289746, 0.6917385, 0.0792869],
+                [0., 0.0451134, 1.0439444],
+            ],
+            Self::Rec2020 => [
+                [0.636958, 0.1446169, 0.168881],
+                [0.2627002, 0.6779981, 0.0593017],
+                [0., 0.0280727, 1.0609851],
+            ],
+            Self::AdobeRgb => [
+                [0.5767309, 0.185554, 0.1881852],
+                [0.2973769, 0.6273491, 0.0752741],
+                [0.0270343, 0.0706872, 0.9911085],
+            ],
+            Self::Custom(m) => m,
+        }
+    }
+
+    /// Linear RGB to LMS of ICtCp (Rec.2100)
+    fn to_lms_matrix(self) -> [[f32; 3]; 3] {
+        let to_lms = [
+            [1688. / 4096., 2146. / 4096., 262. / 4096.],
+            [683. / 4096., 2951. / 4096., 462. / 4096.],
+            [99. / 4096., 309. / 4096., 3688. / 4096.],
+        ];
+        let to_rec2020 = mat_inv(Self::Rec2020.to_xyz_matrix().map(|row| row.map(f64::from)));
+        let m = mat_mul(mat_mul(to_lms, to_rec2020), self.to_xyz_matrix().map(|row| row.map(f64::from)));
+        m.map(|row| row.map(|v| v as f32))
+    }
+}
+
+fn mat_mul(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn mat_inv(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r: usize, c: usize| {
+        let (r1, r2, c1, c2) = ((r + 1) % 3, (r + 2) % 3, (c + 1) % 3, (c + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let det: f64 = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum();
+    std::array::from_fn(|i| std::array::from_fn(|j| cofactor(j, i) / det))
+}
+/// Color model in which the images are compared. See [`Dssim::set_color_model()`][crate::Dssim::set_color_model()]
+///
+/// Other models can be used with [`ColorModel::Custom`], or by implementing [`ToLABBitmap`] for your own image type.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ColorModel {
+    /// CIE L\*a\*b\*. This is the default.
+    #[default]
+    Lab,
+    /// Oklab by Björn Ottosson, which has more uniform hues than L\*a\*b\*
+    Oklab,
+    /// Full-range BT.601 Y′CbCr of sRGB-encoded values, as used by JPEG. The primaries are not converted.
+    YCbCr,
+    /// Your own conversion. Can't be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(CustomColorModel),
+}
+
+/// Conversion of pixels to three planes for [`ColorModel::Custom`]
+///
+/// The pixels are linear RGB with the primaries of the color space set by [`Dssim::set_color_space()`][crate::Dssim::set_color_space()].
+/// The first plane is lightness, and the other two are chroma. They should be roughly in the `0..1` range, like L\*a\*b\* scaled by DSSIM.
+/// Gray images use only the lightness.
+///
+/// Models are equal only if they're clones of the same `CustomColorModel`.
+#[derive(Clone)]
+pub struct CustomColorModel(Arc<dyn Fn(RGBLU) -> [f32; 3] + Send + Sync>);
+
+impl CustomColorModel {
+    pub fn new(convert: impl Fn(RGBLU) -> [f32; 3] + Send + Sync + 'static) -> Self {
+        Self(Arc::new(convert))
+    }
+}
+
+impl fmt::Debug for CustomColorModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomColorModel")
+    }
+}
+
+impl PartialEq for CustomColorModel {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomColorModel {}
+
+/// Oklab's matrix from XYZ to LMS
+const OKLAB_M1: [[f64; 3]; 3] = [
+    [0.8189330101, 0.3618667424, -0.1288597137],
+    [0.0329845436, 0.9293118715, 0.0361456387],
+    [0.0482003018, 0.2643662691, 0.633851707],
+];
+
+/// Converts linear RGB pixels with the given primaries to the color model
+#[derive(Clone)]
+struct ModelConverter {
+    model: ColorModel,
+    m: [[f32; 3]; 3],
+}
+
+impl ModelConverter {
+    fn new(model: ColorModel, color_space: ColorSpace) -> Self {
+        let m = match model {
+            ColorModel::Oklab => {
+                let m = mat_mul(OKLAB_M1, color_space.to_xyz_matrix().map(|row| row.map(f64::from)));
+                m.map(|row| row.map(|v| v as f32))
+            },
+            _ => color_space.to_xyz_matrix(),
+        };
+        Self { model, m }
+    }
+
+    #[inline]
+    fn convert(&self, px: RGBLU) -> (f32, f32, f32) {
+        match self.model {
+            ColorModel::Lab => to_lab_matrix(px, &self.m),
+            ColorModel::Oklab => to_oklab_matrix(px, &self.m),
+            ColorModel::YCbCr => to_ycbcr(px),
+            ColorModel::Custom(ref custom) => {
+                let [l, a, b] = (custom.0)(px);
+                (l, a, b)
+            },
+        }
+    }
+}
+
 pub(crate) trait ToLAB {
     fn to_lab(&self) -> (f32, f32, f32);
 }
@@ -26,6 +204,55 @@ fn fma_matrix(r: f32, rx: f32, g: f32, gx: f32, b: f32, bx: f32) -> f32 {
 const EPSILON: f32 = 216. / 24389.;
 const K: f32 = 24389. / (27. * 116.); // http://www.brucelindbloom.com/LContinuity.html
 
+/// Same as `ToLAB` for sRGB, but for any primaries and without assuming the values are in the sRGB gamut
+fn to_lab_matrix(px: RGBLU, m: &[[f32; 3]; 3]) -> (f32, f32, f32) {
+    let fx = fma_matrix(px.r, m[0][0] / D65x, px.g, m[0][1] / D65x, px.b, m[0][2] / D65x);
+    let fy = fma_matrix(px.r, m[1][0] / D65y, px.g, m[1][1] / D65y, px.b, m[1][2] / D65y);
+    let fz = fma_matrix(px.r, m[2][0] / D65z, px.g, m[2][1] / D65z, px.b, m[2][2] / D65z);
+
+    let X = if fx > EPSILON { fx.cbrt() - 16. / 116. } else { K * fx };
+    let Y = if fy > EPSILON { fy.cbrt() - 16. / 116. } else { K * fy };
+    let Z = if fz > EPSILON { fz.cbrt() - 16. / 116. } else { K * fz };
+
+    (
+        Y * 1.05f32,
+        (500.0 / 220.0f32).mul_add(X - Y, 86.2 / 220.0f32),
+        (200.0 / 220.0f32).mul_add(Y - Z, 107.9 / 220.0f32),
+    )
+}
+
+/// Oklab with chroma moved to positive values. `m` converts to LMS.
+fn to_oklab_matrix(px: RGBLU, m: &[[f32; 3]; 3]) -> (f32, f32, f32) {
+    let l = fma_matrix(px.r, m[0][0], px.g, m[0][1], px.b, m[0][2]).cbrt();
+    let m_ = fma_matrix(px.r, m[1][0], px.g, m[1][1], px.b, m[1][2]).cbrt();
+    let s = fma_matrix(px.r, m[2][0], px.g, m[2][1], px.b, m[2][2]).cbrt();
+    (
+        fma_matrix(l, 0.21045426, m_, 0.7936178, s, -0.004072047),
+        fma_matrix(l, 1.9779985, m_, -2.4285922, s, 0.4505937) + 0.5,
+        fma_matrix(l, 0.025904037, m_, 0.78277177, s, -0.80867577) + 0.5,
+    )
+}
+
+fn to_ycbcr(px: RGBLU) -> (f32, f32, f32) {
+    let (r, g, b) = (to_gamma(px.r), to_gamma(px.g), to_gamma(px.b));
+    let y = fma_matrix(r, 0.299, g, 0.587, b, 0.114);
+    (y, (b - y).mul_add(1. / 1.772, 0.5), (r - y).mul_add(1. / 1.402, 0.5))
+}
+
+/// ICtCp of absolute linear light, with chroma moved to positive values.
+///
+/// Ct is halved, as in ΔE<sub>ITP</sub>, so that all channels are in the same perceptual units.
+fn to_ictcp_matrix(px: RGBLU, m: &[[f32; 3]; 3]) -> (f32, f32, f32) {
+    let l = pq_encode(fma_matrix(px.r, m[0][0], px.g, m[0][1], px.b, m[0][2]));
+    let m_ = pq_encode(fma_matrix(px.r, m[1][0], px.g, m[1][1], px.b, m[1][2]));
+    let s = pq_encode(fma_matrix(px.r, m[2][0], px.g, m[2][1], px.b, m[2][2]));
+    (
+        0.5 * (l + m_),
+        fma_matrix(l, 6610. / 8192., m_, -13613. / 8192., s, 7003. / 8192.) + 0.5,
+        fma_matrix(l, 17933. / 4096., m_, -17390. / 4096., s, -543. / 4096.) + 0.5,
+    )
+}
+
 impl ToLAB for RGBLU {
     fn to_lab(&self) -> (f32, f32, f32) {
         let fx = fma_matrix(self.r, 0.4124 / D65x, self.g, 0.3576 / D65x, self.b, 0.1805 / D65x);
@@ -67,6 +294,57 @@ fn cbrt_poly(x: f32) -> f32 {
 /// It should return 1 (gray) or 3 (color) planes.
 pub trait ToLABBitmap {
     fn to_lab(&self) -> Vec<GBitmap>;
+
+    /// Like `to_lab`, but for RGB pixels with the given primaries, and planes of the given color model.
+    ///
+    /// `None` if the image type supports only sRGB and L\*a\*b\*.
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        (color_space == ColorSpace::Srgb && model == ColorModel::Lab).then(|| self.to_lab())
+    }
+
+    /// Used instead of `to_lab` for HDR. Pixels are absolute linear light, where 1.0 is 10000 cd/m².
+    ///
+    /// Returns PQ-encoded ICtCp planes, where the intensity is in 0..1 and chroma is offset to be positive.
+    ///
+    /// `None` if the image type doesn't support HDR.
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        let _ = color_space;
+        None
+    }
+
+    /// Gamma-encoded luma (Y′ of BT.601 from sRGB), used by the standard SSIM algorithms.
+    ///
+    /// `None` if the image type doesn't support it.
+    fn to_luma(&self) -> Option<GBitmap> {
+        None
+    }
+
+    /// Linear RGB, with transparency flattened the same way as in `to_lab`. Used for PSNR and color difference metrics.
+    ///
+    /// `None` if the image type doesn't support it.
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        None
+    }
+
+    /// Pixels composited over a background, which is given for every `x, y`. Used instead of the default pattern, see [`AlphaMode`][crate::AlphaMode].
+    ///
+    /// `None` if the image has no alpha channel.
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        let _ = background;
+        None
+    }
+
+    /// The alpha channel, compared separately in [`AlphaMode::Separate`][crate::AlphaMode::Separate].
+    ///
+    /// `None` if the image has no alpha channel.
+    fn to_alpha(&self) -> Option<GBitmap> {
+        None
+    }
+}
+
+#[inline]
+fn rgb_to_luma(px: RGBLU) -> f32 {
+    fma_matrix(to_gamma(px.r), 0.299, to_gamma(px.g), 0.587, to_gamma(px.b), 0.114).clamp(0., 1.)
 }
 
 impl ToLABBitmap for ImgVec<RGBAPLU> {
@@ -74,6 +352,30 @@ impl ToLABBitmap for ImgVec<RGBAPLU> {
     fn to_lab(&self) -> Vec<GBitmap> {
         self.as_ref().to_lab()
     }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        self.as_ref().to_luma()
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().to_linear_rgb()
+    }
+
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().composite(background)
+    }
+
+    fn to_alpha(&self) -> Option<GBitmap> {
+        self.as_ref().to_alpha()
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_color_model(color_space, model)
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_ictcp(color_space)
+    }
 }
 
 impl ToLABBitmap for ImgVec<RGBLU> {
@@ -81,10 +383,64 @@ impl ToLABBitmap for ImgVec<RGBLU> {
     fn to_lab(&self) -> Vec<GBitmap> {
         self.as_ref().to_lab()
     }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        self.as_ref().to_luma()
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().to_linear_rgb()
+    }
+
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().composite(background)
+    }
+
+    fn to_alpha(&self) -> Option<GBitmap> {
+        self.as_ref().to_alpha()
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_color_model(color_space, model)
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_ictcp(color_space)
+    }
 }
 impl ToLABBitmap for GBitmap {
+    #[inline(always)]
+    fn to_lab(&self) -> Vec<GBitmap> {
+        self.as_ref().to_lab()
+    }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        self.as_ref().to_luma()
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().to_linear_rgb()
+    }
+
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        self.as_ref().composite(background)
+    }
+
+    fn to_alpha(&self) -> Option<GBitmap> {
+        self.as_ref().to_alpha()
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_color_model(color_space, model)
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        self.as_ref().to_ictcp(color_space)
+    }
+}
+
+impl ToLABBitmap for ImgRef<'_, f32> {
     fn to_lab(&self) -> Vec<GBitmap> {
-        debug_assert!(self.width() > 0);
         let f = |fy| {
             if fy > EPSILON { (cbrt_poly(fy) - 16. / 116.) * 1.16 } else { (K * 1.16) * fy }
         };
@@ -97,18 +453,47 @@ impl ToLABBitmap for GBitmap {
         #[cfg(not(feature = "threads"))]
         let out = self.pixels().map(f).collect();
 
-        vec![Self::new(out, self.width(), self.height())]
+        vec![Img::new(out, self.width(), self.height())]
+    }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        Some(Img::new(self.pixels().map(|l| to_gamma(l).clamp(0., 1.)).collect(), self.width(), self.height()))
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        Some(Img::new(self.pixels().map(|l| RGBLU { r: l, g: l, b: l }).collect(), self.width(), self.height()))
+    }
+
+    /// Gray is luminance in any color space
+    fn to_color_model(&self, _: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        let lightness = match model {
+            ColorModel::Lab => return Some(self.to_lab()),
+            ColorModel::Oklab => |y: f32| y.max(0.).cbrt(),
+            ColorModel::YCbCr => to_gamma,
+            ColorModel::Custom(custom) => {
+                return Some(vec![Img::new(self.pixels().map(|y| (custom.0)(RGBLU { r: y, g: y, b: y })[0]).collect(), self.width(), self.height())]);
+            },
+        };
+        Some(vec![Img::new(self.pixels().map(lightness).collect(), self.width(), self.height())])
+    }
+
+    /// Only the intensity, which for gray is PQ of the luminance
+    fn to_ictcp(&self, _: ColorSpace) -> Option<Vec<GBitmap>> {
+        Some(vec![Img::new(self.pixels().map(pq_encode).collect(), self.width(), self.height())])
     }
 }
 
+/// `top` is the row of the whole image where `img` starts, so that the pattern behind transparent pixels is aligned to the whole image
 #[inline(never)]
-fn rgb_to_lab<T: Copy + Sync + Send + 'static, F>(img: ImgRef<'_, T>, cb: F) -> Vec<GBitmap>
+fn rgb_to_lab<T: Copy + Sync + Send + 'static, F>(img: ImgRef<'_, T>, top: usize, cb: F) -> Vec<GBitmap>
     where F: Fn(T, usize) -> (f32, f32, f32) + Sync + Send + 'static
 {
     let width = img.width();
-    assert!(width > 0);
     let height = img.height();
     let area = width * height;
+    if area == 0 {
+        return vec![Img::new(Vec::new(), width, height); 3];
+    }
 
     let mut out_l = Vec::with_capacity(area);
     let mut out_a = Vec::with_capacity(area);
@@ -125,7 +510,7 @@ fn rgb_to_lab<T: Copy + Sync + Send + 'static, F>(img: ImgRef<'_, T>, cb: F) ->
         let a_row = &mut a_row[0..width];
         let b_row = &mut b_row[0..width];
         for x in 0..width {
-            let n = (x+11) ^ (y+11);
+            let n = (x+11) ^ (top+y+11);
             let (l,a,b) = cb(in_row[x], n);
             l_row[x].write(l);
             a_row[x].write(a);
@@ -147,19 +532,214 @@ fn rgb_to_lab<T: Copy + Sync + Send + 'static, F>(img: ImgRef<'_, T>, cb: F) ->
 impl ToLABBitmap for ImgRef<'_, RGBAPLU> {
     #[inline]
     fn to_lab(&self) -> Vec<GBitmap> {
-        rgb_to_lab(*self, |px, n|{
-            px.to_rgb(n).to_lab()
-        })
+        rgba_to_lab(*self, 0)
+    }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        // Same background for transparent pixels as in to_lab
+        let out = self.rows().enumerate().flat_map(|(y, row)| {
+            row.iter().enumerate().map(move |(x, px)| rgb_to_luma(px.to_rgb((x+11) ^ (y+11))))
+        }).collect();
+        Some(Img::new(out, self.width(), self.height()))
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        Some(rgba_to_linear_rgb(*self, 0))
+    }
+
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        Some(rgba_composite(*self, 0, background))
+    }
+
+    fn to_alpha(&self) -> Option<GBitmap> {
+        Some(Img::new(self.pixels().map(|px| px.a).collect(), self.width(), self.height()))
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        Some(rgba_to_color_model(*self, 0, color_space, model))
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        Some(rgba_to_ictcp(*self, 0, color_space))
     }
 }
 
+/// Rows of a larger image that start at row `top`, used by [`Dssim::compare_tiled()`][crate::Dssim::compare_tiled()].
+///
+/// Backgrounds of transparent pixels are aligned to the whole image, so the band is converted the same as that part of the whole image.
+pub(crate) struct ImageBand {
+    pub pixels: ImgVec<RGBAPLU>,
+    pub top: usize,
+}
+
+impl ToLABBitmap for ImageBand {
+    fn to_lab(&self) -> Vec<GBitmap> {
+        rgba_to_lab(self.pixels.as_ref(), self.top)
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        Some(rgba_to_linear_rgb(self.pixels.as_ref(), self.top))
+    }
+
+    fn composite(&self, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> Option<ImgVec<RGBLU>> {
+        Some(rgba_composite(self.pixels.as_ref(), self.top, background))
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        Some(rgba_to_color_model(self.pixels.as_ref(), self.top, color_space, model))
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        Some(rgba_to_ictcp(self.pixels.as_ref(), self.top, color_space))
+    }
+}
+
+impl Downsample for ImageBand {
+    type Output = Self;
+
+    fn downsample(&self) -> Option<Self::Output> {
+        self.downsample_with(DownsampleFilter::default())
+    }
+
+    // Bands start at rows divisible by every scale, so the half-size band starts at half the row
+    fn downsample_with(&self, filter: DownsampleFilter) -> Option<Self::Output> {
+        Some(Self { pixels: self.pixels.downsample_with(filter)?, top: self.top / 2 })
+    }
+}
+
+fn rgba_to_lab(img: ImgRef<'_, RGBAPLU>, top: usize) -> Vec<GBitmap> {
+    rgb_to_lab(img, top, |px, n|{
+        px.to_rgb(n).to_lab()
+    })
+}
+
+fn rgba_to_linear_rgb(img: ImgRef<'_, RGBAPLU>, top: usize) -> ImgVec<RGBLU> {
+    let out = img.rows().enumerate().flat_map(|(y, row)| {
+        row.iter().enumerate().map(move |(x, px)| px.to_rgb((x+11) ^ (top+y+11)))
+    }).collect();
+    Img::new(out, img.width(), img.height())
+}
+
+fn rgba_composite(img: ImgRef<'_, RGBAPLU>, top: usize, background: &(dyn Fn(usize, usize) -> RGBLU + Sync)) -> ImgVec<RGBLU> {
+    let rows: Vec<_> = img.rows().enumerate().collect();
+    let out: Vec<Vec<RGBLU>> = rows.into_par_iter().map(|(y, row)| {
+        // Premultiplied, so the background fills the rest
+        row.iter().enumerate().map(|(x, px)| {
+            let bg = background(x, top + y);
+            let t = 1. - px.a;
+            RGBLU { r: bg.r.mul_add(t, px.r), g: bg.g.mul_add(t, px.g), b: bg.b.mul_add(t, px.b) }
+        }).collect()
+    }).collect();
+    Img::new(out.concat(), img.width(), img.height())
+}
+
+fn rgba_to_color_model(img: ImgRef<'_, RGBAPLU>, top: usize, color_space: ColorSpace, model: ColorModel) -> Vec<GBitmap> {
+    if color_space == ColorSpace::Srgb && model == ColorModel::Lab {
+        return rgba_to_lab(img, top);
+    }
+    let converter = ModelConverter::new(model, color_space);
+    rgb_to_lab(img, top, move |px, n| converter.convert(px.to_rgb(n)))
+}
+
+fn rgba_to_ictcp(img: ImgRef<'_, RGBAPLU>, top: usize, color_space: ColorSpace) -> Vec<GBitmap> {
+    let m = color_space.to_lms_matrix();
+    rgb_to_lab(img, top, move |px, n| {
+        // The background is SDR white, not the peak brightness
+        let bg = px.to_rgb(n);
+        let px = RGBLU {
+            r: (bg.r - px.r).mul_add(SDR_WHITE, px.r),
+            g: (bg.g - px.g).mul_add(SDR_WHITE, px.g),
+            b: (bg.b - px.b).mul_add(SDR_WHITE, px.b),
+        };
+        to_ictcp_matrix(px, &m)
+    })
+}
+
 impl ToLABBitmap for ImgRef<'_, RGBLU> {
     #[inline]
     fn to_lab(&self) -> Vec<GBitmap> {
-        rgb_to_lab(*self, |px, _n|{
+        rgb_to_lab(*self, 0, |px, _n|{
             px.to_lab()
         })
     }
+
+    fn to_luma(&self) -> Option<GBitmap> {
+        Some(Img::new(self.pixels().map(rgb_to_luma).collect(), self.width(), self.height()))
+    }
+
+    fn to_linear_rgb(&self) -> Option<ImgVec<RGBLU>> {
+        Some(Img::new(self.pixels().collect(), self.width(), self.height()))
+    }
+
+    fn to_color_model(&self, color_space: ColorSpace, model: ColorModel) -> Option<Vec<GBitmap>> {
+        if color_space == ColorSpace::Srgb && model == ColorModel::Lab {
+            return Some(self.to_lab());
+        }
+        let converter = ModelConverter::new(model, color_space);
+        Some(rgb_to_lab(*self, 0, move |px, _n| converter.convert(px)))
+    }
+
+    fn to_ictcp(&self, color_space: ColorSpace) -> Option<Vec<GBitmap>> {
+        let m = color_space.to_lms_matrix();
+        Some(rgb_to_lab(*self, 0, move |px, _n| to_ictcp_matrix(px, &m)))
+    }
+}
+
+#[test]
+fn color_spaces() {
+    let white = RGBLU { r: 1., g: 1., b: 1. };
+    let srgb_white = white.to_lab();
+    for space in [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::Rec2020, ColorSpace::AdobeRgb] {
+        let lab = to_lab_matrix(white, &space.to_xyz_matrix());
+        assert!((lab.0 - srgb_white.0).abs() < 1e-3 && (lab.1 - srgb_white.1).abs() < 1e-3 && (lab.2 - srgb_white.2).abs() < 1e-3, "{space:?} {lab:?}");
+    }
+    // P3 green is outside of sRGB, so it's more saturated than any sRGB green
+    let green = RGBLU { r: 0., g: 1., b: 0. };
+    let p3_green = to_lab_matrix(green, &ColorSpace::DisplayP3.to_xyz_matrix());
+    assert!(p3_green.1 < green.to_lab().1 - 0.01);
+    let srgb = to_lab_matrix(green, &ColorSpace::Srgb.to_xyz_matrix());
+    assert!((srgb.1 - green.to_lab().1).abs() < 1e-3);
+
+    assert!(ColorSpace::Custom([[f32::NAN; 3]; 3]).validate().is_err());
+    assert!(ColorSpace::Custom(ColorSpace::Rec2020.to_xyz_matrix()).validate().is_ok());
+}
+
+#[test]
+fn color_models() {
+    let gray = |v: f32| RGBLU { r: v, g: v, b: v };
+    for space in [ColorSpace::Srgb, ColorSpace::DisplayP3] {
+        let oklab = ModelConverter::new(ColorModel::Oklab, space);
+        let (l, a, b) = oklab.convert(gray(1.));
+        assert!((l - 1.).abs() < 1e-3 && (a - 0.5).abs() < 1e-3 && (b - 0.5).abs() < 1e-3, "{space:?} {l} {a} {b}");
+        assert!((oklab.convert(gray(0.18)).0 - 0.18f32.cbrt()).abs() < 1e-3);
+    }
+    // Reference values of Oklab for sRGB red
+    let (l, a, b) = ModelConverter::new(ColorModel::Oklab, ColorSpace::Srgb).convert(RGBLU { r: 1., g: 0., b: 0. });
+    assert!((l - 0.62796).abs() < 1e-3 && (a - 0.5 - 0.22486).abs() < 1e-3 && (b - 0.5 - 0.12585).abs() < 1e-3, "{l} {a} {b}");
+
+    let ycbcr = ModelConverter::new(ColorModel::YCbCr, ColorSpace::Srgb);
+    let (y, cb, cr) = ycbcr.convert(gray(0.5));
+    assert!((y - to_gamma(0.5)).abs() < 1e-5 && (cb - 0.5).abs() < 1e-5 && (cr - 0.5).abs() < 1e-5);
+    let (_, cb, cr) = ycbcr.convert(RGBLU { r: 0., g: 0., b: 1. });
+    assert!((cb - 1.).abs() < 1e-5 && cr < 0.5);
+}
+
+#[test]
+fn ictcp() {
+    let gray = |v: f32| RGBLU { r: v, g: v, b: v };
+    for space in [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::Rec2020] {
+        let m = space.to_lms_matrix();
+        let (i, ct, cp) = to_ictcp_matrix(gray(SDR_WHITE), &m);
+        assert!((i - pq_encode(SDR_WHITE)).abs() < 1e-4 && (ct - 0.5).abs() < 1e-3 && (cp - 0.5).abs() < 1e-3, "{space:?} {i} {ct} {cp}");
+        assert!((to_ictcp_matrix(gray(1.), &m).0 - 1.).abs() < 1e-3);
+    }
+    // Brighter than SDR white is still distinguishable, unlike in L*a*b* which has no headroom
+    let m = ColorSpace::Rec2020.to_lms_matrix();
+    let (i1, _, _) = to_ictcp_matrix(gray(0.05), &m);
+    let (i2, _, _) = to_ictcp_matrix(gray(0.1), &m);
+    assert!(i2 - i1 > 0.05 && i2 < 1.);
+    let red = to_ictcp_matrix(RGBLU { r: SDR_WHITE, g: 0., b: 0. }, &m);
+    assert!(red.2 > 0.6);
 }
 
 #[test]
@@ -6,6 +6,7 @@ use std::ops::{Add, Div, Mul, Sub};
 
 /// Result of comparison as `f64`
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 pub struct Dssim(f64);
 
 impl Dssim {
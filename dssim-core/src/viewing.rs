@@ -0,0 +1,86 @@
+//! Scale weights for the resolution at which images are seen. See [`Dssim::set_viewing_conditions()`][crate::Dssim::set_viewing_conditions()]
+
+use crate::error::Error;
+
+/// Scales whose finest details are coarser than this are not used
+const MIN_CYCLES_PER_DEGREE: f64 = 1.;
+const MAX_SCALES: usize = 8;
+
+/// How large the pixels are for the viewer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewingConditions {
+    pixels_per_degree: f64,
+}
+
+impl ViewingConditions {
+    /// Number of pixels in one degree of the visual field. Typical desktop monitors have about 40, and phones about 90.
+    ///
+    /// Fails if it's not in `2..=1000` range.
+    pub fn from_pixels_per_degree(pixels_per_degree: f64) -> Result<Self, Error> {
+        if !(pixels_per_degree >= 2. && pixels_per_degree <= 1000.) {
+            return Err(Error::InvalidParameter("pixels per degree must be in 2..=1000 range"));
+        }
+        Ok(Self { pixels_per_degree })
+    }
+
+    /// Display with the given pixel density, in pixels per inch, seen from the distance in centimeters
+    ///
+    /// Fails if the resulting pixels per degree is out of range, see [`ViewingConditions::from_pixels_per_degree()`].
+    pub fn from_display(pixels_per_inch: f64, distance_cm: f64) -> Result<Self, Error> {
+        let degree_cm = 2. * distance_cm * 0.5f64.to_radians().tan();
+        Self::from_pixels_per_degree(pixels_per_inch * degree_cm / 2.54)
+    }
+
+    #[must_use]
+    pub fn pixels_per_degree(&self) -> f64 {
+        self.pixels_per_degree
+    }
+
+    /// Weights of scales, starting from the full-resolution one, which sum to 1.
+    ///
+    /// Each scale is weighed by the contrast sensitivity of human vision (Mannos & Sakrison) at the Nyquist frequency of that scale,
+    /// and scales with details coarser than 1 cycle per degree are left out.
+    #[must_use]
+    pub fn scale_weights(&self) -> Vec<f64> {
+        let mut weights: Vec<f64> = (0..MAX_SCALES)
+            .map(|n| self.pixels_per_degree / f64::from(2 << n))
+            .enumerate()
+            .take_while(|&(n, cycles_per_degree)| n == 0 || cycles_per_degree >= MIN_CYCLES_PER_DEGREE)
+            .map(|(_, cycles_per_degree)| contrast_sensitivity(cycles_per_degree))
+            .collect();
+        let sum: f64 = weights.iter().sum();
+        for w in &mut weights {
+            *w /= sum;
+        }
+        weights
+    }
+}
+
+/// Relative sensitivity to contrast at a spatial frequency in cycles per degree, which peaks at about 8
+fn contrast_sensitivity(cycles_per_degree: f64) -> f64 {
+    let f = 0.114 * cycles_per_degree;
+    2.6 * (0.0192 + f) * (-f.powf(1.1)).exp()
+}
+
+#[test]
+fn weights() {
+    let desktop = ViewingConditions::from_display(100., 60.).unwrap();
+    assert!((desktop.pixels_per_degree() - 41.2).abs() < 0.1, "{}", desktop.pixels_per_degree());
+    let phone = ViewingConditions::from_display(460., 30.).unwrap();
+    assert!((phone.pixels_per_degree() - 94.8).abs() < 0.1, "{}", phone.pixels_per_degree());
+
+    let desktop_weights = desktop.scale_weights();
+    let phone_weights = phone.scale_weights();
+    assert_eq!(5, desktop_weights.len());
+    assert_eq!(6, phone_weights.len());
+    for w in [&desktop_weights, &phone_weights] {
+        assert!((w.iter().sum::<f64>() - 1.).abs() < 1e-9);
+        assert!(w.iter().all(|&w| w > 0.));
+    }
+    // Fine details are less visible on dense displays
+    assert!(phone_weights[0] < desktop_weights[0] / 2.);
+
+    assert_eq!(1, ViewingConditions::from_pixels_per_degree(2.).unwrap().scale_weights().len());
+    assert!(ViewingConditions::from_pixels_per_degree(f64::NAN).is_err());
+    assert!(ViewingConditions::from_display(100., 0.).is_err());
+}
@@ -0,0 +1,90 @@
+//! `compare_tiled` has to stay within its memory limit. This is a separate test binary, because it counts all allocations.
+
+use dssim_core::{AlphaMode, Dssim, DownsampleFilter};
+use imgref::ImgVec;
+use rgb::RGBA;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn allocated(size: usize) {
+    let now = CURRENT.fetch_add(size, Ordering::SeqCst) + size;
+    PEAK.fetch_max(now, Ordering::SeqCst);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            allocated(new_size);
+            CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Peak of memory allocated by the callback, in addition to what has been allocated before
+fn peak_memory<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let res = f();
+    (res, PEAK.load(Ordering::SeqCst) - before)
+}
+
+fn load(path: &str, copies: usize) -> ImgVec<RGBA<u8>> {
+    let file = lodepng::decode32_file(path).unwrap();
+    let buf: Vec<_> = (0..copies).flat_map(|_| file.buffer.iter().copied()).collect();
+    ImgVec::new(buf, file.width, file.height * copies)
+}
+
+fn check_peak_memory(d: &Dssim, img1: &ImgVec<RGBA<u8>>, img2: &ImgVec<RGBA<u8>>) {
+    let (w, h) = (img1.width(), img1.height());
+    let (whole, whole_memory) = peak_memory(|| {
+        let (score, _) = d.compare(&d.create_image_rgba(img1.buf(), w, h).unwrap(), d.create_image_rgba(img2.buf(), w, h).unwrap()).unwrap();
+        score
+    });
+
+    for max_memory in [whole_memory / 2, whole_memory / 3] {
+        let (bands, peak) = peak_memory(|| d.compare_tiled(img1.as_ref(), img2.as_ref(), max_memory).unwrap());
+        assert!(peak <= max_memory, "{peak} > {max_memory}");
+        assert!((whole - bands).abs() < 1e-5, "{whole} {bands}");
+    }
+}
+
+#[test]
+fn tiled_peak_memory() {
+    let img1 = load("../tests/test1-sm.png", 12);
+    let img2 = load("../tests/test2-sm.png", 12);
+    let (w, h) = (img1.width(), img1.height());
+
+    let mut d = dssim_core::new();
+    // Starts the thread pool
+    d.compare_tiled(img1.sub_image(0, 0, w, 8), img2.sub_image(0, 0, w, 8), usize::MAX).unwrap();
+    check_peak_memory(&d, &img1, &img2);
+
+    // Flattened pixels and the resampling filter need more
+    let translucent = ImgVec::new(img2.pixels().enumerate().map(|(i, px)| RGBA { a: (i * 7 % 256) as u8, ..px }).collect(), w, h);
+    d.set_alpha_mode(AlphaMode::Checkerboard { size: 5, light: [200, 200, 200], dark: [50, 50, 50] }).unwrap();
+    d.set_downsample_filter(DownsampleFilter::Lanczos2);
+    check_peak_memory(&d, &img1, &translucent);
+}
@@ -3,11 +3,11 @@
 #![allow(clippy::manual_range_contains)]
 
 pub use dssim_core::*;
-use imgref::Img;
+use imgref::{Img, ImgVec};
 use load_image::ImageData;
 use std::path::Path;
 
-fn load(attr: &Dssim, path: &Path) -> Result<DssimImage<f32>, load_image::Error> {
+fn load(attr: &Dssim, path: &Path) -> Result<DssimImage<f32>, Box<dyn std::error::Error + Send + Sync>> {
     let img = load_image::load_path(path)?;
     Ok(match img.bitmap {
         ImageData::RGB8(ref bitmap) => attr.create_image(&Img::new(bitmap.to_rgblu(), img.width, img.height)),
@@ -18,11 +18,27 @@ fn load(attr: &Dssim, path: &Path) -> Result<DssimImage<f32>, load_image::Error>
         ImageData::GRAY16(ref bitmap) => attr.create_image(&Img::new(bitmap.to_rgblu(), img.width, img.height)),
         ImageData::GRAYA8(ref bitmap) => attr.create_image(&Img::new(bitmap.to_rgbaplu(), img.width, img.height)),
         ImageData::GRAYA16(ref bitmap) => attr.create_image(&Img::new(bitmap.to_rgbaplu(), img.width, img.height)),
-    }.expect("infallible"))
+    }?)
 }
 
 /// Load PNG or JPEG image from the given path. Applies color profiles and converts to `sRGB`.
 #[inline]
-pub fn load_image(attr: &Dssim, path: impl AsRef<Path>) -> Result<DssimImage<f32>, load_image::Error> {
+pub fn load_image(attr: &Dssim, path: impl AsRef<Path>) -> Result<DssimImage<f32>, Box<dyn std::error::Error + Send + Sync>> {
     load(attr, path.as_ref())
 }
+
+/// Load image as pixels, without creating [`DssimImage`]. Use it with [`Dssim::compare_images()`] for images that may have different sizes.
+pub fn load_image_pixels(path: impl AsRef<Path>) -> Result<ImgVec<RGBAPLU>, Box<dyn std::error::Error + Send + Sync>> {
+    let img = load_image::load_path(path.as_ref())?;
+    let pixels = match img.bitmap {
+        ImageData::RGB8(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::RGB16(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::RGBA8(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::RGBA16(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::GRAY8(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::GRAY16(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::GRAYA8(ref bitmap) => bitmap.to_rgbaplu(),
+        ImageData::GRAYA16(ref bitmap) => bitmap.to_rgbaplu(),
+    };
+    Ok(Img::new(pixels, img.width, img.height))
+}
@@ -17,7 +17,9 @@
  * If not, see <http://www.gnu.org/licenses/agpl.txt>.
  */
 #![allow(clippy::manual_range_contains)]
+use dssim::{DssimImage, SizeAdjustment, SizePolicy, RGBAPLU};
 use getopts::Options;
+use imgref::ImgVec;
 #[cfg(feature = "threads")]
 use rayon::prelude::*;
 use std::env;
@@ -30,7 +32,12 @@ fn usage(argv0: &str) {
      \n   or: {argv0} -o difference.png original.png modified.png\n\n\
        Compares first image against subsequent images, and outputs\n\
        1/SSIM-1 difference for each of them in order (0 = identical).\n\n\
-       Images must have identical size, but may have different gamma & depth.\n\
+       Images must have identical size, unless --size is used,\n\
+       but may have different gamma & depth.\n\n\
+       --size crop      compare only the top-left area common to both images\n\
+       --size X,Y       compare the overlap when the modified image is at X,Y\n\
+       --size resample  resize modified images to the size of the original\n\
+       --align N        find and undo shifts of up to N pixels before comparing\n\
        \nVersion {} https://kornel.ski/dssim\n", env!("CARGO_PKG_VERSION"));
 }
 
@@ -41,6 +48,24 @@ fn to_byte(i: f32) -> u8 {
     else {(i * 256.0) as u8}
 }
 
+enum Loaded {
+    Image(DssimImage<f32>),
+    /// Kept as pixels, so that they can be cropped or resampled
+    Pixels(ImgVec<RGBAPLU>),
+}
+
+fn parse_size_policy(arg: &str) -> Option<SizePolicy> {
+    Some(match arg {
+        "exact" => SizePolicy::Exact,
+        "crop" => SizePolicy::CropToIntersection,
+        "resample" => SizePolicy::Resample,
+        offset => {
+            let (x, y) = offset.split_once(',')?;
+            SizePolicy::CropAt { x: x.trim().parse().ok()?, y: y.trim().parse().ok()? }
+        },
+    })
+}
+
 fn main() -> ExitCode {
     if let Err(e) = run() {
         eprintln!("error: {e}");
@@ -59,6 +84,8 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut opts = Options::new();
     opts.optopt("o", "", "set output file name", "NAME");
+    opts.optopt("", "size", "policy for images of different sizes", "crop|resample|X,Y");
+    opts.optopt("", "align", "max shift in pixels to search for when aligning images", "N");
     opts.optflag("h", "help", "print this help menu");
     let matches = opts.parse(args)?;
 
@@ -70,6 +97,15 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
     let map_output_file_tmp = matches.opt_str("o");
     let map_output_file = map_output_file_tmp.as_ref();
 
+    let size_policy = match matches.opt_str("size") {
+        Some(arg) => parse_size_policy(&arg).ok_or_else(|| format!("Invalid --size value: {arg}"))?,
+        None => SizePolicy::Exact,
+    };
+    let max_alignment_shift = match matches.opt_str("align") {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid --align value: {arg}"))?,
+        None => 0,
+    };
+
     let files = matches.free;
 
     if files.len() < 2 {
@@ -83,12 +119,20 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
     if map_output_file.is_some() {
         attr.set_save_ssim_maps(8);
     }
+    attr.set_size_policy(size_policy);
+    attr.set_alignment(max_alignment_shift);
+    let load_pixels = size_policy != SizePolicy::Exact || max_alignment_shift > 0;
 
     std::thread::scope(|scope| {
         let decode_thread = || {
             let images_send = images_send; // ensure it's moved, and attr isn't
             filenames_recv.into_iter().try_for_each(|(i, file): (usize, PathBuf)| {
-                dssim::load_image(&attr, &file)
+                let loaded = if load_pixels {
+                    dssim::load_image_pixels(&file).map(Loaded::Pixels)
+                } else {
+                    dssim::load_image(&attr, &file).map(Loaded::Image)
+                };
+                loaded
                     .map_err(|e| format!("Can't load {}, because: {e}", file.display()))
                     .and_then(|image| images_send.send(i, (file, image)).map_err(|_| "Aborted".into()))
             })
@@ -104,15 +148,42 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
                 .try_for_each(move |f| filenames_send.send(f))?;
 
             let (file1, original) = images_recv.next().ok_or("Can't load any images")?;
+            let original_image = match &original {
+                Loaded::Pixels(original) => Some(attr.create_image(original)
+                    .map_err(|e| format!("Can't use {}, because: {e}", file1.display()))?),
+                Loaded::Image(_) => None,
+            };
 
             for (file2, modified) in images_recv {
-                if original.width() != modified.width() || original.height() != modified.height() {
-                    return Err(format!("Image {} has a different size ({}x{}) than {} ({}x{})\n",
-                        file2.display(), modified.width(), modified.height(),
-                        file1.display(), original.width(), original.height()).into());
-                }
-
-                let (dssim, ssim_maps) = attr.compare(&original, modified);
+                let (dssim, ssim_maps) = match (&original, modified) {
+                    (Loaded::Image(original), Loaded::Image(modified)) => {
+                        if original.width() != modified.width() || original.height() != modified.height() {
+                            return Err(format!("Image {} has a different size ({}x{}) than {} ({}x{})\n",
+                                file2.display(), modified.width(), modified.height(),
+                                file1.display(), original.width(), original.height()).into());
+                        }
+                        attr.compare(original, modified)?
+                    },
+                    (Loaded::Pixels(original), Loaded::Pixels(modified)) => {
+                        let Some(original_image) = &original_image else { unreachable!() };
+                        let report = attr.compare_images_with_original(original.as_ref(), original_image, modified.as_ref())
+                            .map_err(|e| format!("Can't compare {} with {}, because: {e}", file2.display(), file1.display()))?;
+                        match report.size_adjustment {
+                            SizeAdjustment::Cropped { left, top, width, height } => {
+                                eprintln!("note: compared {width}x{height} area at {left},{top} of {}", file1.display());
+                            },
+                            SizeAdjustment::Resampled { width, height } => {
+                                eprintln!("note: resampled {} from {width}x{height}", file2.display());
+                            },
+                            _ => {},
+                        }
+                        if let Some(a) = report.alignment.filter(|a| a.dx != 0. || a.dy != 0.) {
+                            eprintln!("note: {} is shifted by {:.2},{:.2}; compared {}x{} area at {},{}", file2.display(), a.dx, a.dy, a.width, a.height, a.left, a.top);
+                        }
+                        (report.dssim, report.scales.into_iter().filter_map(|s| s.ssim_map).collect())
+                    },
+                    _ => unreachable!(),
+                };
 
                 println!("{dssim:.8}\t{}", file2.display());
 
@@ -162,13 +233,13 @@ fn image_gray() {
     let g3 = dssim::load_image(&attr, "tests/gray1-gray.png").unwrap();
     let g4 = dssim::load_image(&attr, "tests/gray1.jpg").unwrap();
 
-    let (diff, _) = attr.compare(&g1, g2);
+    let (diff, _) = attr.compare(&g1, g2).unwrap();
     assert!(diff < 0.00001, "{diff}");
 
-    let (diff, _) = attr.compare(&g1, g3);
+    let (diff, _) = attr.compare(&g1, g3).unwrap();
     assert!(diff < 0.00001, "{diff}");
 
-    let (diff, _) = attr.compare(&g1, g4);
+    let (diff, _) = attr.compare(&g1, g4).unwrap();
     assert!(diff < 0.00006, "{diff}");
 }
 
@@ -180,10 +251,10 @@ fn image_gray_profile() {
     let gp2 = dssim::load_image(&attr, "tests/gray-profile2.png").unwrap();
     let gp3 = dssim::load_image(&attr, "tests/gray-profile.jpg").unwrap();
 
-    let (diff, _) = attr.compare(&gp1, gp2);
+    let (diff, _) = attr.compare(&gp1, gp2).unwrap();
     assert!(diff < 0.0003, "{}", diff);
 
-    let (diff, _) = attr.compare(&gp1, gp3);
+    let (diff, _) = attr.compare(&gp1, gp3).unwrap();
     assert!(diff < 0.0003, "{}", diff);
 }
 
@@ -192,15 +263,15 @@ fn image_load1() {
     let attr = dssim::Dssim::new();
     let prof_jpg = dssim::load_image(&attr, "tests/profile.jpg").unwrap();
     let prof_png = dssim::load_image(&attr, "tests/profile.png").unwrap();
-    let (diff, _) = attr.compare(&prof_jpg, prof_png);
+    let (diff, _) = attr.compare(&prof_jpg, prof_png).unwrap();
     assert!(diff <= 0.002);
 
     let strip_jpg = dssim::load_image(&attr, "tests/profile-stripped.jpg").unwrap();
-    let (diff, _) = attr.compare(&strip_jpg, prof_jpg);
+    let (diff, _) = attr.compare(&strip_jpg, prof_jpg).unwrap();
     assert!(diff > 0.008, "{}", diff);
 
     let strip_png = dssim::load_image(&attr, "tests/profile-stripped.png").unwrap();
-    let (diff, _) = attr.compare(&strip_jpg, strip_png);
+    let (diff, _) = attr.compare(&strip_jpg, strip_png).unwrap();
     assert!(diff > 0.009, "{}", diff);
 }
 
@@ -212,5 +283,5 @@ fn rgblu_input() {
     let ctx = Dssim::new();
     let im: ImgVec<RGBLU> = Img::new(vec![rgb::RGB::new(0., 0., 0.)], 1, 1);
     let imr: ImgRef<'_, RGBLU> = im.as_ref();
-    ctx.create_image(&imr);
+    ctx.create_image(&imr).unwrap();
 }